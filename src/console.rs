@@ -0,0 +1,477 @@
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Instr {
+    Acc(isize),
+    Jmp(isize),
+    Nop(isize),
+}
+
+impl Instr {
+    // nop <-> jmp, the only corruption the boot code can have
+    pub fn flipped(&self) -> Option<Instr> {
+        match *self {
+            Instr::Nop(j) => Some(Instr::Jmp(j)),
+            Instr::Jmp(j) => Some(Instr::Nop(j)),
+            Instr::Acc(_) => None
+        }
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instr::Acc(x) => write!(f, "acc {:+}", x),
+            Instr::Jmp(j) => write!(f, "jmp {:+}", j),
+            Instr::Nop(j) => write!(f, "nop {:+}", j),
+        }
+    }
+}
+
+pub type Program = Vec<Instr>;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    MissingOperand,
+    InvalidOperand(String),
+    InvalidLabel(String),
+    TrailingInput(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown instruction `{}`", m),
+            AsmErrorKind::MissingOperand => write!(f, "missing operand"),
+            AsmErrorKind::InvalidOperand(o) => write!(f, "invalid operand `{}`", o),
+            AsmErrorKind::InvalidLabel(l) => write!(f, "invalid label name `{}`", l),
+            AsmErrorKind::TrailingInput(t) => write!(f, "unexpected `{}` after operand", t),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "label `{}` defined twice", l),
+            AsmErrorKind::UndefinedLabel(l) => write!(f, "undefined label `{}`", l),
+        }
+    }
+}
+
+enum Operand<'a> {
+    Value(isize),
+    Label(&'a str),
+}
+
+struct SourceInstr<'a> {
+    line: usize,
+    mnemonic: &'a str,
+    operand: Operand<'a>,
+}
+
+fn is_label(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_operand(s: &str) -> Option<Operand<'_>> {
+    let unsigned = s.strip_prefix('+').unwrap_or(s);
+
+    if let Ok(value) = unsigned.parse::<isize>() {
+        Some(Operand::Value(value))
+    } else if is_label(s) {
+        Some(Operand::Label(s))
+    } else {
+        None
+    }
+}
+
+// Accepts the puzzle format plus `#`/`;` comments, blank lines, and `name:` labels (either on
+// their own line or before an instruction). jmp/nop operands may name a label instead of an offset.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut instrs: Vec<SourceInstr> = vec![];
+
+    for (i, raw_line) in source.lines().enumerate() {
+        let line = i + 1;
+        let err = |kind| AsmError { line, kind };
+
+        let mut text = raw_line.split(['#', ';']).next().unwrap().trim();
+
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+
+            if !is_label(label) {
+                return Err(err(AsmErrorKind::InvalidLabel(label.to_string())));
+            }
+
+            if labels.insert(label, instrs.len()).is_some() {
+                return Err(err(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+
+            text = text[colon + 1..].trim();
+        }
+
+        let mut words = text.split_whitespace();
+
+        let mnemonic = match words.next() {
+            Some(mnemonic) => mnemonic,
+            None => continue
+        };
+
+        if !["acc", "jmp", "nop"].contains(&mnemonic) {
+            return Err(err(AsmErrorKind::UnknownMnemonic(mnemonic.to_string())));
+        }
+
+        let operand_text = words.next().ok_or_else(|| err(AsmErrorKind::MissingOperand))?;
+        let operand = parse_operand(operand_text)
+            .ok_or_else(|| err(AsmErrorKind::InvalidOperand(operand_text.to_string())))?;
+
+        if let Some(extra) = words.next() {
+            return Err(err(AsmErrorKind::TrailingInput(extra.to_string())));
+        }
+
+        if let (Operand::Label(label), "acc") = (&operand, mnemonic) {
+            return Err(err(AsmErrorKind::InvalidOperand(label.to_string())));
+        }
+
+        instrs.push(SourceInstr { line, mnemonic, operand });
+    }
+
+    instrs.iter().enumerate().map(|(pc, instr)| {
+        let value = match instr.operand {
+            Operand::Value(value) => value,
+            Operand::Label(label) => match labels.get(label) {
+                Some(&target) => target as isize - pc as isize,
+                None => return Err(AsmError {
+                    line: instr.line,
+                    kind: AsmErrorKind::UndefinedLabel(label.to_string())
+                })
+            }
+        };
+
+        Ok(match instr.mnemonic {
+            "acc" => Instr::Acc(value),
+            "jmp" => Instr::Jmp(value),
+            _ => Instr::Nop(value),
+        })
+    }).collect()
+}
+
+// canonical puzzle-format text, one instruction per line
+pub fn disassemble(program: &[Instr]) -> String {
+    program.iter().map(|instr| format!("{}\n", instr)).collect()
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Registers {
+    pub acc: isize,
+    pub pc: usize,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LoopDetection {
+    Disabled,
+    // report a loop when an instruction is about to run more than this many times
+    MaxVisits(usize),
+}
+
+impl Default for LoopDetection {
+    fn default() -> LoopDetection {
+        LoopDetection::MaxVisits(1)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RunResult {
+    Terminated(isize),
+    Loop { acc: isize, pc: usize, pcs: BTreeSet<usize> },
+    OutOfBounds { acc: isize, pc: usize, target: isize },
+    Breakpoint(usize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Status {
+    Running,
+    Halted(RunResult),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub instr: Instr,
+    pub acc_before: isize,
+    pub acc_after: isize,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+    pub result: RunResult,
+}
+
+impl Trace {
+    pub fn record(program: Program) -> Trace {
+        let mut vm = Vm::new(program).with_trace();
+        let result = vm.run();
+
+        Trace { entries: vm.trace().to_vec(), result }
+    }
+
+    // the pc that was about to run a second time when the loop was detected
+    pub fn repeated_pc(&self) -> Option<usize> {
+        match self.result {
+            RunResult::Loop { pc, .. } => Some(pc),
+            _ => None
+        }
+    }
+
+    fn outcome(&self) -> String {
+        match &self.result {
+            RunResult::Terminated(acc) => format!("terminated with acc {}", acc),
+            RunResult::Loop { acc, pc, .. } => format!("loop detected: pc {} repeats with acc {}", pc, acc),
+            RunResult::OutOfBounds { acc, pc, target } =>
+                format!("pc {} jumped out of bounds to {} with acc {}", pc, target, acc),
+            RunResult::Breakpoint(pc) => format!("stopped at breakpoint {}", pc),
+        }
+    }
+
+    pub fn write_text<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let repeated_pc = self.repeated_pc();
+
+        for (step, entry) in self.entries.iter().enumerate() {
+            let marker = if Some(entry.pc) == repeated_pc { "  <- repeats" } else { "" };
+
+            writeln!(
+                out, "{:>6} {:>6}  {:<10} {:>8} -> {}{}",
+                step, entry.pc, entry.instr.to_string(), entry.acc_before, entry.acc_after, marker
+            )?;
+        }
+
+        writeln!(out, "{}", self.outcome())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let entries: Vec<Value> = self.entries.iter().map(|entry| {
+            json!({
+                "pc": entry.pc,
+                "instr": entry.instr.to_string(),
+                "acc_before": entry.acc_before,
+                "acc_after": entry.acc_after,
+            })
+        }).collect();
+
+        json!({
+            "entries": entries,
+            "repeated_pc": self.repeated_pc(),
+            "outcome": self.outcome(),
+        })
+    }
+
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, &self.to_json())?;
+
+        writeln!(out)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Vm {
+    program: Program,
+    registers: Registers,
+    loop_detection: LoopDetection,
+    breakpoints: HashSet<usize>,
+    visits: Vec<usize>,
+    trace: Option<Vec<TraceEntry>>,
+    paused_at: Option<usize>,
+    halted: Option<RunResult>,
+}
+
+// where control goes after running `instr` at `pc`, before bounds checking
+pub fn next_pc(pc: usize, instr: Instr) -> isize {
+    match instr {
+        Instr::Acc(_) | Instr::Nop(_) => pc as isize + 1,
+        Instr::Jmp(j) => pc as isize + j,
+    }
+}
+
+// for each pc, whether running from there (on the unmodified program) ends by stepping onto
+// `program.len()`; computed by walking the reversed control-flow edges back from the end
+pub fn terminating_pcs(program: &[Instr]) -> Vec<bool> {
+    let len = program.len();
+    let mut predecessors: Vec<Vec<usize>> = vec![vec![]; len + 1];
+
+    for (pc, &instr) in program.iter().enumerate() {
+        let target = next_pc(pc, instr);
+
+        if 0 <= target && target as usize <= len {
+            predecessors[target as usize].push(pc);
+        }
+    }
+
+    let mut terminating = vec![false; len + 1];
+    let mut to_visit = vec![len];
+    terminating[len] = true;
+
+    while let Some(pc) = to_visit.pop() {
+        for &prev in predecessors[pc].iter() {
+            if !terminating[prev] {
+                terminating[prev] = true;
+                to_visit.push(prev);
+            }
+        }
+    }
+
+    terminating.truncate(len);
+
+    terminating
+}
+
+impl Vm {
+    pub fn new(program: Program) -> Vm {
+        let len = program.len();
+
+        Vm {
+            program,
+            registers: Registers::default(),
+            loop_detection: LoopDetection::default(),
+            breakpoints: HashSet::new(),
+            visits: vec![0; len],
+            trace: None,
+            paused_at: None,
+            halted: None,
+        }
+    }
+
+    pub fn with_loop_detection(self, loop_detection: LoopDetection) -> Vm {
+        Vm { loop_detection, ..self }
+    }
+
+    pub fn with_trace(self) -> Vm {
+        Vm { trace: Some(vec![]), ..self }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    pub fn add_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.remove(&pc);
+    }
+
+    pub fn reset(&mut self) {
+        self.registers = Registers::default();
+        self.visits = vec![0; self.program.len()];
+        self.trace = self.trace.as_ref().map(|_| vec![]);
+        self.paused_at = None;
+        self.halted = None;
+    }
+
+    fn halt(&mut self, result: RunResult) -> Status {
+        self.halted = Some(result.clone());
+
+        Status::Halted(result)
+    }
+
+    // the pcs visited when following control flow from `start` until it comes back around
+    fn loop_pcs(&self, start: usize) -> BTreeSet<usize> {
+        let mut pcs = BTreeSet::new();
+        let mut pc = start;
+
+        while pcs.insert(pc) {
+            match next_pc(pc, self.program[pc]) {
+                next if next < 0 || next as usize >= self.program.len() => break,
+                next => { pc = next as usize; }
+            }
+        }
+
+        pcs
+    }
+
+    pub fn step(&mut self) -> Status {
+        if let Some(result) = &self.halted {
+            return Status::Halted(result.clone());
+        }
+
+        let Registers { acc, pc } = self.registers;
+
+        if pc == self.program.len() {
+            return self.halt(RunResult::Terminated(acc));
+        }
+
+        if let LoopDetection::MaxVisits(max) = self.loop_detection {
+            if self.visits[pc] >= max {
+                let pcs = self.loop_pcs(pc);
+
+                return self.halt(RunResult::Loop { acc, pc, pcs });
+            }
+        }
+
+        self.visits[pc] += 1;
+
+        let instr = self.program[pc];
+        let new_acc = match instr {
+            Instr::Acc(x) => acc + x,
+            _ => acc,
+        };
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceEntry { pc, instr, acc_before: acc, acc_after: new_acc });
+        }
+
+        self.registers.acc = new_acc;
+
+        let target = next_pc(pc, instr);
+
+        if target < 0 || target as usize > self.program.len() {
+            return self.halt(RunResult::OutOfBounds { acc: new_acc, pc, target });
+        }
+
+        self.registers.pc = target as usize;
+
+        if self.registers.pc == self.program.len() {
+            return self.halt(RunResult::Terminated(new_acc));
+        }
+
+        Status::Running
+    }
+
+    // runs until the program halts or reaches a breakpoint; calling again resumes past it
+    pub fn run(&mut self) -> RunResult {
+        loop {
+            let pc = self.registers.pc;
+
+            if self.halted.is_none() && self.breakpoints.contains(&pc) && self.paused_at != Some(pc) {
+                self.paused_at = Some(pc);
+
+                return RunResult::Breakpoint(pc);
+            }
+
+            self.paused_at = None;
+
+            if let Status::Halted(result) = self.step() {
+                return result;
+            }
+        }
+    }
+}
@@ -1,66 +1,200 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use std::collections::HashMap;
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt;
 
-#[aoc_generator(day10)]
-fn parse_joltages(input: &str) -> Vec<usize> {
-    input.lines().map(|l| l.parse().unwrap()).sorted().collect()
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct JoltageRules {
+    // an adapter accepts any input from min_step to max_step jolts below its rating
+    pub min_step: usize,
+    pub max_step: usize,
+    pub outlet: usize,
+    // how far the device's built-in adapter is above the highest rated adapter
+    pub device_offset: usize,
 }
 
-#[aoc(day10, part1)]
-fn jolt_diff_product(input: &Vec<usize>) -> usize {
-    let mut last = 0usize;
-    let mut counts: HashMap<usize, usize> = HashMap::new();
-
-    input.iter().for_each(|&v| {
-        assert!(v - last > 0 && v - last <= 3);
+impl JoltageRules {
+    fn accepts(&self, from: usize, to: usize) -> bool {
+        from + self.min_step <= to && to <= from + self.max_step
+    }
+}
 
-        *counts.entry(v - last).or_insert(0) += 1;
-        last = v;
-    });
+impl Default for JoltageRules {
+    fn default() -> JoltageRules {
+        JoltageRules { min_step: 1, max_step: 3, outlet: 0, device_offset: 3 }
+    }
+}
 
-    counts.get(&1usize).unwrap() * (counts.get(&3usize).unwrap() + 1)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChainError {
+    Gap { from: usize, to: usize },
+    TooClose { from: usize, to: usize },
 }
 
-#[aoc(day10, part2)]
-fn jolt_count_arrangements(input: &Vec<usize>) -> usize {
-    let mut cache = HashMap::new();
-
-    input.iter().take(3).map(|&v| {
-        if v <= 3 {
-            _count_arrangements_cached(input, &mut cache)
-        } else {
-            0
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainError::Gap { from, to } => write!(f, "no adapter can bridge {} to {} jolts", from, to),
+            ChainError::TooClose { from, to } => write!(f, "a {} jolt adapter can't take {} jolts", to, from),
         }
-    }).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdapterChain {
+    rules: JoltageRules,
+    // the outlet, every adapter in order, then the device
+    joltages: Vec<usize>,
+    // ways[i] is the number of arrangements that get from joltages[i] to the device
+    ways: Vec<BigUint>,
 }
 
-fn _count_arrangements_cached(input: &[usize], cache: &mut HashMap<usize, usize>) -> usize {
-    let val = match &input[..] {
-        [] => 1,
-        [_] => 1,
-        [current, rest @ ..] => {
-            // first check the cache!
-            if let Some(v) = cache.get(current) {
-                *v
+impl AdapterChain {
+    pub fn new(adapters: &[usize]) -> Result<AdapterChain, ChainError> {
+        AdapterChain::with_rules(adapters, JoltageRules::default())
+    }
+
+    pub fn with_rules(adapters: &[usize], rules: JoltageRules) -> Result<AdapterChain, ChainError> {
+        let mut joltages = vec![rules.outlet];
+        joltages.extend(adapters.iter().sorted());
+        joltages.push(joltages.last().unwrap() + rules.device_offset);
+
+        for (&from, &to) in joltages.iter().tuple_windows() {
+            if to < from + rules.min_step {
+                return Err(ChainError::TooClose { from, to });
+            }
+
+            if to > from + rules.max_step {
+                return Err(ChainError::Gap { from, to });
+            }
+        }
+
+        let mut chain = AdapterChain { rules, ways: vec![BigUint::zero(); joltages.len()], joltages };
+
+        for i in (0..chain.joltages.len()).rev() {
+            chain.ways[i] = if i == chain.device() {
+                BigUint::one()
             } else {
-                // can check up to 3 next items
-                let next_3 = rest.iter().take(3);
+                chain.successors(i).map(|next| &chain.ways[next]).sum()
+            };
+        }
+
+        Ok(chain)
+    }
+
+    fn device(&self) -> usize {
+        self.joltages.len() - 1
+    }
+
+    fn successors(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        let from = self.joltages[i];
+
+        (i + 1..self.joltages.len())
+            .take_while(move |&next| self.joltages[next] <= from + self.rules.max_step)
+            .filter(move |&next| self.rules.accepts(from, self.joltages[next]))
+    }
+
+    // counts of each joltage difference when every adapter is used, device included
+    pub fn histogram(&self) -> BTreeMap<usize, usize> {
+        let mut counts = BTreeMap::new();
+
+        for (from, to) in self.joltages.iter().tuple_windows() {
+            *counts.entry(to - from).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    pub fn count_arrangements(&self) -> BigUint {
+        self.ways[0].clone()
+    }
 
-                let sum_vals = next_3.enumerate().map(|(index, v)| {
-                    if v - current <= 3 {
-                        _count_arrangements_cached(&rest[index..], cache)
-                    } else {
-                        0
-                    }
-                }).sum();
+    pub fn arrangements(&self) -> Arrangements<'_> {
+        Arrangements { chain: self, path: None }
+    }
 
-                cache.insert(*current, sum_vals);
+    // picks uniformly among all arrangements: each step is weighted by how many ways remain from there
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<usize> {
+        let mut adapters = vec![];
+        let mut i = 0;
 
-                sum_vals
+        while i != self.device() {
+            let mut pick = rng.gen_biguint_below(&self.ways[i]);
+
+            for next in self.successors(i) {
+                if pick < self.ways[next] {
+                    i = next;
+                    break;
+                }
+
+                pick -= &self.ways[next];
+            }
+
+            if i != self.device() {
+                adapters.push(self.joltages[i]);
             }
         }
-    };
 
-    val
+        adapters
+    }
+}
+
+// Walks arrangements in lexicographic order of adapter positions, starting from the one that uses
+// every adapter. Each step finds the last hop that can skip one more adapter and then takes every
+// adapter after it, which is always valid since consecutive adapters already satisfy the rules.
+pub struct Arrangements<'a> {
+    chain: &'a AdapterChain,
+    path: Option<Vec<usize>>,
+}
+
+impl<'a> Iterator for Arrangements<'a> {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let joltages = &self.chain.joltages;
+        let rules = &self.chain.rules;
+        let len = joltages.len();
+
+        let path = match self.path.as_mut() {
+            None => self.path.insert((0..len).collect()),
+            Some(path) => {
+                let skip_from = (0..path.len() - 1).rev().find(|&k| {
+                    let candidate = path[k + 1] + 1;
+
+                    candidate < len && rules.accepts(joltages[path[k]], joltages[candidate])
+                })?;
+
+                let candidate = path[skip_from + 1] + 1;
+                path.truncate(skip_from + 1);
+                path.extend(candidate..len);
+
+                path
+            }
+        };
+
+        Some(path[1..path.len() - 1].iter().map(|&i| joltages[i]).collect())
+    }
+}
+
+#[aoc_generator(day10)]
+pub fn parse_joltages(input: &str) -> Vec<usize> {
+    input.lines().map(|l| l.parse().unwrap()).sorted().collect()
+}
+
+#[aoc(day10, part1)]
+fn jolt_diff_product(input: &Vec<usize>) -> usize {
+    let chain = AdapterChain::new(input).unwrap_or_else(|err| panic!("{}", err));
+    let counts = chain.histogram();
+
+    counts.get(&1).unwrap_or(&0) * counts.get(&3).unwrap_or(&0)
+}
+
+#[aoc(day10, part2)]
+fn jolt_count_arrangements(input: &Vec<usize>) -> BigUint {
+    let chain = AdapterChain::new(input).unwrap_or_else(|err| panic!("{}", err));
+
+    chain.count_arrangements()
 }
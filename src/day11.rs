@@ -1,16 +1,40 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum Seat {
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Seat {
     Floor,
     Empty,
     Occupied
 }
 
-type State = Vec<Vec<Seat>>;
+impl Seat {
+    fn to_char(self) -> char {
+        match self {
+            Seat::Floor => '.',
+            Seat::Empty => 'L',
+            Seat::Occupied => '#',
+        }
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        match self {
+            Seat::Floor => [40, 40, 40],
+            Seat::Empty => [60, 160, 60],
+            Seat::Occupied => [200, 50, 50],
+        }
+    }
+}
+
+pub type State = Vec<Vec<Seat>>;
 
 #[aoc_generator(day11)]
-fn parse_initial_state(input: &str) -> State {
+pub fn parse_initial_state(input: &str) -> State {
     input.lines().map(|line| {
         line.chars().map(|c| {
             match c {
@@ -23,140 +47,264 @@ fn parse_initial_state(input: &str) -> State {
     }).collect()
 }
 
-fn step_state(state: &State) -> State {
-    let mut new_state = state.clone();
-
-    let rows = new_state.len() as i32;
-    let cols  = new_state.first().unwrap().len() as i32;
-
-    state.iter().enumerate().for_each(|(y, row)| {
-        row.iter().enumerate().for_each(|(x, seat)| {
-            let coord_diffs = vec![
-                (-1, -1),
-                (-1, 0),
-                (-1, 1),
-                (0, -1),
-                (0, 1),
-                (1, -1),
-                (1, 0),
-                (1, 1)
-            ];
-
-            let num_adjacent_occupied_seats: usize = coord_diffs.iter().map(|(y_inc, x_inc)| {
-                let new_y = y as i32 + y_inc;
-                let new_x = x as i32 + x_inc;
-
-                if 0 <= new_y && new_y < rows
-                    && 0 <= new_x && new_x < cols
-                    && state[new_y as usize][new_x as usize] == Seat::Occupied {
-                    1
-                } else {
-                    0
-                }
-            }).sum();
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NeighbourMode {
+    Adjacent,
+    FirstVisible,
+}
 
-            match seat {
-                Seat::Occupied if num_adjacent_occupied_seats >= 4 => {
-                    new_state[y][x] = Seat::Empty;
-                },
-                Seat::Empty if num_adjacent_occupied_seats == 0 => {
-                    new_state[y][x] = Seat::Occupied;
-                },
-                _ => ()
-            };
-        });
-    });
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SeatingRules {
+    pub neighbours: NeighbourMode,
+    // an occupied seat empties when at least this many of its neighbours are occupied
+    pub leave_threshold: usize,
+    // an empty seat fills when at most this many of its neighbours are occupied
+    pub sit_threshold: usize,
+}
+
+const ADJACENT_RULES: SeatingRules =
+    SeatingRules { neighbours: NeighbourMode::Adjacent, leave_threshold: 4, sit_threshold: 0 };
+
+const FIRST_VISIBLE_RULES: SeatingRules =
+    SeatingRules { neighbours: NeighbourMode::FirstVisible, leave_threshold: 5, sit_threshold: 0 };
+
+const DIRECTIONS: [(i32, i32); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1)
+];
 
-    new_state
+// the layout first seen at generation `start` comes back every `period` generations;
+// a period of 1 means the layout has settled
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Cycle {
+    pub start: usize,
+    pub period: usize,
 }
 
-fn step_state_2(state: &State) -> State {
-    let mut new_state = state.clone();
-
-    let rows = new_state.len() as i32;
-    let cols  = new_state.first().unwrap().len() as i32;
-
-    state.iter().enumerate().for_each(|(y, row)| {
-        row.iter().enumerate().for_each(|(x, seat)| {
-            let coord_diffs = vec![
-                (-1, -1),
-                (-1, 0),
-                (-1, 1),
-                (0, -1),
-                (0, 1),
-                (1, -1),
-                (1, 0),
-                (1, 1)
-            ];
-
-            let num_adjacent_occupied_seats: usize = coord_diffs.iter().map(|(y_inc, x_inc)| {
-                let mut new_y = y as i32 + y_inc;
-                let mut new_x = x as i32 + x_inc;
-
-                while 0 <= new_y && new_y < rows
-                    && 0 <= new_x && new_x < cols
-                    && state[new_y as usize][new_x as usize] == Seat::Floor {
-                    new_y += y_inc;
-                    new_x += x_inc;
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FrameFormat {
+    Text,
+    Ppm { scale: usize },
+}
+
+// Seats are kept in a flat row-major vec. Floor never changes, so which seats count as a
+// seat's neighbours is fixed up front and each generation only looks at those lists.
+#[derive(Debug, Clone)]
+pub struct SeatingSimulator {
+    rules: SeatingRules,
+    cols: usize,
+    seats: Vec<Seat>,
+    neighbours: Vec<Vec<usize>>,
+    // hash of every distinct layout seen so far, to each layout with that hash and the
+    // generation it first appeared in; a hit only counts as a repeat if the layout matches
+    seen: HashMap<u64, Vec<(usize, Vec<Seat>)>>,
+    occupancy: Vec<usize>,
+    cycle: Option<Cycle>,
+}
+
+impl SeatingSimulator {
+    pub fn new(state: &State, rules: SeatingRules) -> SeatingSimulator {
+        let rows = state.len() as i32;
+        let cols = state.first().map_or(0, |row| row.len()) as i32;
+
+        let is_seat = |y: i32, x: i32| state[y as usize][x as usize] != Seat::Floor;
+        let in_bounds = |y: i32, x: i32| 0 <= y && y < rows && 0 <= x && x < cols;
+
+        let neighbours = (0..rows).flat_map(|y| (0..cols).map(move |x| (y, x))).map(|(y, x)| {
+            if !is_seat(y, x) {
+                return vec![];
+            }
+
+            DIRECTIONS.iter().filter_map(|(y_inc, x_inc)| {
+                let mut new_y = y + y_inc;
+                let mut new_x = x + x_inc;
+
+                if rules.neighbours == NeighbourMode::FirstVisible {
+                    while in_bounds(new_y, new_x) && !is_seat(new_y, new_x) {
+                        new_y += y_inc;
+                        new_x += x_inc;
+                    }
                 }
 
-                if 0 <= new_y && new_y < rows
-                    && 0 <= new_x && new_x < cols
-                    && state[new_y as usize][new_x as usize] == Seat::Occupied {
-                    1
+                if in_bounds(new_y, new_x) && is_seat(new_y, new_x) {
+                    Some((new_y * cols + new_x) as usize)
                 } else {
-                    0
+                    None
                 }
-            }).sum();
+            }).collect()
+        }).collect();
+
+        let mut simulator = SeatingSimulator {
+            rules,
+            cols: cols as usize,
+            seats: state.iter().flatten().cloned().collect(),
+            neighbours,
+            seen: HashMap::new(),
+            occupancy: vec![],
+            cycle: None,
+        };
+
+        simulator.record_generation();
+
+        simulator
+    }
+
+    fn record_generation(&mut self) {
+        let generation = self.occupancy.len();
+        self.occupancy.push(self.occupied());
+
+        let mut hasher = DefaultHasher::new();
+        self.seats.hash(&mut hasher);
+
+        let candidates = self.seen.entry(hasher.finish()).or_default();
+        let seats = &self.seats;
+
+        if let Some(&(start, _)) = candidates.iter().find(|(_, layout)| layout == seats) {
+            if self.cycle.is_none() {
+                self.cycle = Some(Cycle { start, period: generation - start });
+            }
+        } else {
+            candidates.push((generation, seats.clone()));
+        }
+    }
+
+    // returns whether any seat changed
+    pub fn step(&mut self) -> bool {
+        let new_seats: Vec<Seat> = self.seats.iter().enumerate().map(|(i, &seat)| {
+            let occupied_neighbours = self.neighbours[i].iter().filter(|&&n| {
+                self.seats[n] == Seat::Occupied
+            }).count();
 
             match seat {
-                Seat::Occupied if num_adjacent_occupied_seats >= 5 => {
-                    new_state[y][x] = Seat::Empty;
-                },
-                Seat::Empty if num_adjacent_occupied_seats == 0 => {
-                    new_state[y][x] = Seat::Occupied;
-                },
-                _ => ()
+                Seat::Occupied if occupied_neighbours >= self.rules.leave_threshold => Seat::Empty,
+                Seat::Empty if occupied_neighbours <= self.rules.sit_threshold => Seat::Occupied,
+                _ => seat
+            }
+        }).collect();
+
+        let changed = new_seats != self.seats;
+        self.seats = new_seats;
+        self.record_generation();
+
+        changed
+    }
+
+    pub fn generation(&self) -> usize {
+        self.occupancy.len() - 1
+    }
+
+    // occupied seat count for every generation so far, starting with the initial layout
+    pub fn occupancy(&self) -> &[usize] {
+        &self.occupancy
+    }
+
+    pub fn cycle(&self) -> Option<Cycle> {
+        self.cycle
+    }
+
+    pub fn run_until_repeat(&mut self) -> Cycle {
+        loop {
+            if let Some(cycle) = self.cycle {
+                return cycle;
+            }
+
+            self.step();
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.seats.chunks(self.cols.max(1)).map(|row| row.to_vec()).collect()
+    }
+
+    pub fn occupied(&self) -> usize {
+        self.seats.iter().filter(|&&seat| seat == Seat::Occupied).count()
+    }
+
+    // Settling is decided by `step` seeing no seat change; the repeat tracking is only there to
+    // stop on a layout that oscillates, which is returned as the error.
+    pub fn run_until_settled(&mut self) -> Result<usize, Cycle> {
+        while self.step() {
+            if let Some(cycle) = self.cycle.filter(|cycle| cycle.period > 1) {
+                return Err(cycle);
+            }
+        }
+
+        Ok(self.occupied())
+    }
+
+    pub fn write_text_frame<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "generation {}: {} occupied", self.generation(), self.occupied())?;
+
+        for row in self.seats.chunks(self.cols.max(1)) {
+            writeln!(out, "{}", row.iter().map(|&seat| seat.to_char()).collect::<String>())?;
+        }
+
+        writeln!(out)
+    }
+
+    // binary PPM, each seat drawn as a scale x scale block
+    pub fn write_ppm_frame<W: Write>(&self, out: &mut W, scale: usize) -> io::Result<()> {
+        let rows = self.seats.len() / self.cols.max(1);
+
+        write!(out, "P6\n{} {}\n255\n", self.cols * scale, rows * scale)?;
+
+        for row in self.seats.chunks(self.cols.max(1)) {
+            for _ in 0..scale {
+                for &seat in row {
+                    for _ in 0..scale {
+                        out.write_all(&seat.to_rgb())?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Runs until a layout repeats, writing every generation (including the current one) to `dir`
+    // as frame_00000.txt, frame_00001.txt, ... or the .ppm equivalent.
+    pub fn dump_frames(&mut self, dir: &Path, format: FrameFormat) -> io::Result<Cycle> {
+        loop {
+            let extension = match format {
+                FrameFormat::Text => "txt",
+                FrameFormat::Ppm { .. } => "ppm",
             };
-        });
-    });
 
-    new_state
-}
+            let path = dir.join(format!("frame_{:05}.{}", self.generation(), extension));
+            let mut out = BufWriter::new(File::create(path)?);
 
-fn _count_occupied_seats(state: &State) -> usize {
-    state.iter().map(|row| {
-        row.iter().map(|seat| {
-            match seat {
-                Seat::Occupied => 1,
-                _ => 0
+            match format {
+                FrameFormat::Text => self.write_text_frame(&mut out)?,
+                FrameFormat::Ppm { scale } => self.write_ppm_frame(&mut out, scale)?,
             }
-        }).sum::<usize>()
-    }).sum::<usize>()
-}
 
-#[aoc(day11, part1)]
-fn count_occupied_seats_after_settled(state: &State) -> usize {
-    let mut new_state = state.clone();
-    let mut mutated_state = step_state(state);
+            out.flush()?;
 
-    while new_state != mutated_state {
-        new_state = mutated_state;
-        mutated_state = step_state(&new_state);
+            if let Some(cycle) = self.cycle {
+                return Ok(cycle);
+            }
+
+            self.step();
+        }
     }
+}
 
-    _count_occupied_seats(&new_state)
+#[aoc(day11, part1)]
+fn count_occupied_seats_after_settled(state: &State) -> usize {
+    SeatingSimulator::new(state, ADJACENT_RULES).run_until_settled().unwrap_or_else(|cycle| {
+        panic!("seating never settles, it oscillates every {} generations", cycle.period)
+    })
 }
 
 #[aoc(day11, part2)]
 fn count_occupied_seats_after_settled_2(state: &State) -> usize {
-    let mut new_state = state.clone();
-    let mut mutated_state = step_state_2(state);
-
-    while new_state != mutated_state {
-        new_state = mutated_state;
-        mutated_state = step_state_2(&new_state);
-    }
-
-    _count_occupied_seats(&new_state)
+    SeatingSimulator::new(state, FIRST_VISIBLE_RULES).run_until_settled().unwrap_or_else(|cycle| {
+        panic!("seating never settles, it oscillates every {} generations", cycle.period)
+    })
 }
@@ -1,17 +1,19 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::str::FromStr;
+use std::fmt;
+use std::io::{self, Write};
 use itertools::Itertools;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum Dir {
+pub enum Dir {
     N,
     S,
     E,
     W
 }
 
-#[derive(Debug, Copy, Clone)]
-enum Instr {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Instr {
     N(isize),
     S(isize),
     E(isize),
@@ -21,44 +23,267 @@ enum Instr {
     F(isize)
 }
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum InstrParseError {
+    Empty,
+    UnknownAction(char),
+    InvalidValue(String),
+    // distances and angles are magnitudes; direction comes from the action
+    NegativeValue { action: char, value: isize },
+    UnsupportedAngle(isize),
+}
+
+impl fmt::Display for InstrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstrParseError::Empty => write!(f, "empty instruction"),
+            InstrParseError::UnknownAction(c) => write!(f, "unknown action `{}`", c),
+            InstrParseError::InvalidValue(v) => write!(f, "invalid value `{}`", v),
+            InstrParseError::NegativeValue { action, value } =>
+                write!(f, "negative value {} for action `{}`", value, action),
+            InstrParseError::UnsupportedAngle(a) =>
+                write!(f, "{} degree turn is not a multiple of 90 (needs continuous headings)", a),
+        }
+    }
+}
+
 impl FromStr for Instr {
-    type Err = &'static str;
+    type Err = InstrParseError;
 
     fn from_str(input: &str) -> Result<Instr, Self::Err> {
-        let chars = input.chars().collect::<Vec<char>>();
-        match &chars[..] {
-            ['N', rest @ ..] => Ok(Instr::N(rest.into_iter().collect::<String>().parse().unwrap())),
-            ['S', rest @ ..] => Ok(Instr::S(rest.into_iter().collect::<String>().parse().unwrap())),
-            ['E', rest @ ..] => Ok(Instr::E(rest.into_iter().collect::<String>().parse().unwrap())),
-            ['W', rest @ ..] => Ok(Instr::W(rest.into_iter().collect::<String>().parse().unwrap())),
-            ['F', rest @ ..] => Ok(Instr::F(rest.into_iter().collect::<String>().parse().unwrap())),
-            ['L', rest @ ..] => Ok(Instr::L(rest.into_iter().collect::<String>().parse().unwrap())),
-            ['R', rest @ ..] => Ok(Instr::R(rest.into_iter().collect::<String>().parse().unwrap())),
-            _ => Err("unparseable instruction")
+        let mut chars = input.trim().chars();
+        let action = chars.next().ok_or(InstrParseError::Empty)?;
+        let value: isize = chars.as_str().parse()
+            .map_err(|_| InstrParseError::InvalidValue(chars.as_str().to_string()))?;
+
+        if value < 0 {
+            return Err(InstrParseError::NegativeValue { action, value });
+        }
+
+        match action {
+            'N' => Ok(Instr::N(value)),
+            'S' => Ok(Instr::S(value)),
+            'E' => Ok(Instr::E(value)),
+            'W' => Ok(Instr::W(value)),
+            'F' => Ok(Instr::F(value)),
+            'L' => Ok(Instr::L(value)),
+            'R' => Ok(Instr::R(value)),
+            _ => Err(InstrParseError::UnknownAction(action))
         }
     }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TurnMode {
+    // turns must be whole quarter turns, as `Ship` and `WaypointShip` expect
+    RightAngles,
+    // any angle, for `ContinuousShip`; only quarter turns are exact there
+    Continuous,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: InstrParseError,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+pub fn parse_instructions_with(input: &str, mode: TurnMode) -> Result<Vec<Instr>, ParseError> {
+    input.lines().enumerate().map(|(i, line)| {
+        let err = |kind| ParseError { line: i + 1, kind };
+
+        match line.parse::<Instr>().map_err(err)? {
+            Instr::L(angle) | Instr::R(angle) if mode == TurnMode::RightAngles && angle % 90 != 0 =>
+                Err(err(InstrParseError::UnsupportedAngle(angle))),
+            instr => Ok(instr)
+        }
+    }).collect()
+}
+
 #[aoc_generator(day12)]
-fn parse_instructions(input: &str) -> Vec<Instr> {
-    input.lines().map(|line| line.parse::<Instr>().unwrap()).collect()
+pub fn parse_instructions(input: &str) -> Vec<Instr> {
+    parse_instructions_with(input, TurnMode::RightAngles).unwrap_or_else(|err| panic!("{}", err))
+}
+
+impl Instr {
+    // Undoes this instruction for either kind of ship. Going back along the heading (or the
+    // waypoint) means turning around, sailing forward the same amount, and turning back.
+    pub fn inverse(&self) -> Vec<Instr> {
+        match *self {
+            Instr::N(dist) => vec![Instr::S(dist)],
+            Instr::S(dist) => vec![Instr::N(dist)],
+            Instr::E(dist) => vec![Instr::W(dist)],
+            Instr::W(dist) => vec![Instr::E(dist)],
+            Instr::L(angle) => vec![Instr::R(angle)],
+            Instr::R(angle) => vec![Instr::L(angle)],
+            Instr::F(dist) => vec![Instr::R(180), Instr::F(dist), Instr::L(180)],
+        }
+    }
+}
+
+// the instructions that take a ship from the end of `log` back to where it started
+pub fn inverse(log: &[Instr]) -> Vec<Instr> {
+    log.iter().rev().flat_map(|instr| instr.inverse()).collect()
+}
+
+fn quarter_turns(angle: isize) -> isize {
+    assert!(angle % 90 == 0, "{}", InstrParseError::UnsupportedAngle(angle));
+
+    angle / 90
 }
 
-trait Navigable {
-    fn execute(&self, instrs: &[Instr]) -> Self;
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TrajectoryPoint {
+    pub x: isize,
+    pub y: isize,
+    // relative to the ship, for navigators that steer by waypoint
+    pub waypoint: Option<(isize, isize)>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BoundingBox {
+    pub min_x: isize,
+    pub max_x: isize,
+    pub min_y: isize,
+    pub max_y: isize,
+}
+
+impl BoundingBox {
+    // the smallest box holding every point, or `None` if there are none
+    fn around(mut points: impl Iterator<Item = (isize, isize)>) -> Option<BoundingBox> {
+        let (x, y) = points.next()?;
+
+        Some(points.fold(
+            BoundingBox { min_x: x, max_x: x, min_y: y, max_y: y },
+            |bb, (x, y)| BoundingBox {
+                min_x: bb.min_x.min(x),
+                max_x: bb.max_x.max(x),
+                min_y: bb.min_y.min(y),
+                max_y: bb.max_y.max(y),
+            }
+        ))
+    }
+}
+
+// the position (and waypoint) before the first instruction and after each one
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Trajectory {
+    pub points: Vec<TrajectoryPoint>,
+}
+
+impl Trajectory {
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        BoundingBox::around(self.points.iter().map(|p| (p.x, p.y)))
+    }
+
+    // straight-line distance along the path, so waypoint moves count diagonally
+    pub fn distance_travelled(&self) -> f64 {
+        self.points.iter().tuple_windows().map(|(a, b)| {
+            ((b.x - a.x) as f64).hypot((b.y - a.y) as f64)
+        }).sum()
+    }
+
+    // north is up, so y is flipped to match SVG's downward axis; an empty trajectory gets a
+    // blank view around the origin
+    pub fn write_svg<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let bb = BoundingBox::around(self.points.iter().flat_map(|p| {
+            let tip = p.waypoint.map(|(wx, wy)| (p.x + wx, p.y + wy));
+
+            std::iter::once((p.x, p.y)).chain(tip)
+        })).unwrap_or(BoundingBox { min_x: 0, max_x: 0, min_y: 0, max_y: 0 });
+
+        let width = (bb.max_x - bb.min_x).max(1);
+        let height = (bb.max_y - bb.min_y).max(1);
+        let margin = width.max(height) / 20 + 1;
+        let stroke = width.max(height) as f64 / 500.0;
+
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+            bb.min_x - margin, -bb.max_y - margin, width + 2 * margin, height + 2 * margin
+        )?;
+
+        for p in self.points.iter() {
+            if let Some((wx, wy)) = p.waypoint {
+                writeln!(
+                    out,
+                    "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"orange\" stroke-width=\"{}\"/>",
+                    p.x, -p.y, p.x + wx, -(p.y + wy), stroke / 2.0
+                )?;
+            }
+        }
+
+        let path = self.points.iter().map(|p| format!("{},{}", p.x, -p.y)).join(" ");
+        writeln!(
+            out,
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"{}\"/>",
+            path, stroke
+        )?;
+
+        if let (Some(first), Some(last)) = (self.points.first(), self.points.last()) {
+            writeln!(out, "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"green\"/>", first.x, -first.y, stroke * 4.0)?;
+            writeln!(out, "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"red\"/>", last.x, -last.y, stroke * 4.0)?;
+        }
+
+        writeln!(out, "</svg>")
+    }
+}
+
+pub trait Navigable: Clone {
+    fn step(&self, instr: &Instr) -> Self;
+
+    fn position(&self) -> (isize, isize);
+
+    fn waypoint(&self) -> Option<(isize, isize)> {
+        None
+    }
 
     fn manhattan_dist(&self, other: &Self) -> usize;
+
+    fn execute(&self, instrs: &[Instr]) -> Self {
+        instrs.iter().fold(self.clone(), |ship, instr| ship.step(instr))
+    }
+
+    fn record(&self, instrs: &[Instr]) -> Trajectory {
+        let point = |ship: &Self| {
+            let (x, y) = ship.position();
+
+            TrajectoryPoint { x, y, waypoint: ship.waypoint() }
+        };
+
+        let mut ship = self.clone();
+        let mut points = vec![point(&ship)];
+
+        for instr in instrs {
+            ship = ship.step(instr);
+            points.push(point(&ship));
+        }
+
+        Trajectory { points }
+    }
+
+    // whether running `log` and then its inverse leaves the ship exactly as it started
+    fn round_trip_is_identity(&self, log: &[Instr]) -> bool where Self: PartialEq {
+        let round_trip: Vec<Instr> = log.iter().cloned().chain(inverse(log)).collect();
+
+        self.execute(&round_trip) == *self
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
-struct Ship {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Ship {
     pos_x: isize,
     pos_y: isize,
     dir: Dir,
 }
 
 impl Ship {
-    fn new(dir: Dir) -> Ship {
+    pub fn new(dir: Dir) -> Ship {
         Ship {
             pos_x: 0,
             pos_y: 0,
@@ -73,7 +298,7 @@ impl Ship {
 
         match instr {
             Instr::L(angle) => {
-                let num_rotations = angle / 90;
+                let num_rotations = quarter_turns(*angle);
 
                 let (index, _) = dirs.iter().find_position(|&&d| d == ship.dir).unwrap();
                 let new_index = (index as isize - num_rotations).rem_euclid(4) as usize;
@@ -81,7 +306,7 @@ impl Ship {
                 Ship { dir: dirs[new_index], ..ship }
             },
             Instr::R(angle) => {
-                let num_rotations = angle / 90;
+                let num_rotations = quarter_turns(*angle);
 
                 let (index, _) = dirs.iter().find_position(|&&d| d == ship.dir).unwrap();
                 let new_index = (index as isize + num_rotations).rem_euclid(4) as usize;
@@ -94,22 +319,26 @@ impl Ship {
 }
 
 impl Navigable for Ship {
-    fn execute(&self, instrs: &[Instr]) -> Ship {
-        instrs.iter().fold(self.clone(), |ship, instr| {
-            match instr {
-                Instr::N(dist) => Ship{ pos_y: ship.pos_y + dist, ..ship },
-                Instr::S(dist) => Ship{ pos_y: ship.pos_y - dist, ..ship },
-                Instr::E(dist) => Ship{ pos_x: ship.pos_x + dist, ..ship },
-                Instr::W(dist) => Ship{ pos_x: ship.pos_x - dist, ..ship },
-                Instr::F(dist) => match ship.dir {
-                    Dir::N => Ship{ pos_y: ship.pos_y + dist, ..ship },
-                    Dir::S => Ship{ pos_y: ship.pos_y - dist, ..ship },
-                    Dir::E => Ship{ pos_x: ship.pos_x + dist, ..ship },
-                    Dir::W => Ship{ pos_x: ship.pos_x - dist, ..ship }
-                }
-                turn => ship.turn_ship(turn)
+    fn step(&self, instr: &Instr) -> Ship {
+        let ship = *self;
+
+        match instr {
+            Instr::N(dist) => Ship{ pos_y: ship.pos_y + dist, ..ship },
+            Instr::S(dist) => Ship{ pos_y: ship.pos_y - dist, ..ship },
+            Instr::E(dist) => Ship{ pos_x: ship.pos_x + dist, ..ship },
+            Instr::W(dist) => Ship{ pos_x: ship.pos_x - dist, ..ship },
+            Instr::F(dist) => match ship.dir {
+                Dir::N => Ship{ pos_y: ship.pos_y + dist, ..ship },
+                Dir::S => Ship{ pos_y: ship.pos_y - dist, ..ship },
+                Dir::E => Ship{ pos_x: ship.pos_x + dist, ..ship },
+                Dir::W => Ship{ pos_x: ship.pos_x - dist, ..ship }
             }
-        })
+            turn => ship.turn_ship(turn)
+        }
+    }
+
+    fn position(&self) -> (isize, isize) {
+        (self.pos_x, self.pos_y)
     }
 
     fn manhattan_dist(&self, other_ship: &Ship) -> usize {
@@ -127,8 +356,8 @@ fn manhattan_dist(instrs: &Vec<Instr>) -> usize {
     new_ship.manhattan_dist(&ship)
 }
 
-#[derive(Debug, Copy, Clone)]
-struct Waypoint {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Waypoint {
     rel_x: isize,
     rel_y: isize,
 }
@@ -152,6 +381,7 @@ impl Waypoint {
 
     fn turn_waypoint_clockwise(&self, num_rotations: &isize) -> Waypoint {
         match num_rotations {
+            0 => *self,
             1 => Waypoint{ rel_y: -self.rel_x, rel_x: self.rel_y },
             2 => Waypoint{ rel_y: -self.rel_y, rel_x: -self.rel_x },
             3 => Waypoint{ rel_y: self.rel_x, rel_x: -self.rel_y },
@@ -160,15 +390,15 @@ impl Waypoint {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-struct WaypointShip {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct WaypointShip {
     pos_x: isize,
     pos_y: isize,
     waypoint: Waypoint
 }
 
 impl WaypointShip {
-    fn new(init_waypoint_x: isize, init_waypoint_y: isize) -> WaypointShip {
+    pub fn new(init_waypoint_x: isize, init_waypoint_y: isize) -> WaypointShip {
         WaypointShip{
             pos_x: 0,
             pos_y: 0,
@@ -220,7 +450,7 @@ impl WaypointShip {
 
         match instr {
             Instr::L(angle) => {
-                let num_rotations = (360 - angle) / 90;
+                let num_rotations = (-quarter_turns(*angle)).rem_euclid(4);
 
                 WaypointShip {
                     waypoint: self.waypoint.turn_waypoint_clockwise(&num_rotations),
@@ -228,7 +458,7 @@ impl WaypointShip {
                 }
             },
             Instr::R(angle) => {
-                let num_rotations = angle / 90;
+                let num_rotations = quarter_turns(*angle).rem_euclid(4);
 
                 WaypointShip {
                     waypoint: self.waypoint.turn_waypoint_clockwise(&num_rotations),
@@ -241,17 +471,23 @@ impl WaypointShip {
 }
 
 impl Navigable for WaypointShip {
-    fn execute(&self, instrs: &[Instr]) -> WaypointShip {
-        instrs.iter().fold(self.clone(), |ship, instr| {
-            match instr {
-                Instr::N(dist) => ship.move_waypoint_north(dist),
-                Instr::S(dist) => ship.move_waypoint_south(dist),
-                Instr::E(dist) => ship.move_waypoint_east(dist),
-                Instr::W(dist) => ship.move_waypoint_west(dist),
-                Instr::F(times) => ship.move_to_waypoint(times),
-                turn => ship.turn_waypoint(turn)
-            }
-        })
+    fn step(&self, instr: &Instr) -> WaypointShip {
+        match instr {
+            Instr::N(dist) => self.move_waypoint_north(dist),
+            Instr::S(dist) => self.move_waypoint_south(dist),
+            Instr::E(dist) => self.move_waypoint_east(dist),
+            Instr::W(dist) => self.move_waypoint_west(dist),
+            Instr::F(times) => self.move_to_waypoint(times),
+            turn => self.turn_waypoint(turn)
+        }
+    }
+
+    fn position(&self) -> (isize, isize) {
+        (self.pos_x, self.pos_y)
+    }
+
+    fn waypoint(&self) -> Option<(isize, isize)> {
+        Some((self.waypoint.rel_x, self.waypoint.rel_y))
     }
 
     fn manhattan_dist(&self, other_ship: &WaypointShip) -> usize {
@@ -262,6 +498,143 @@ impl Navigable for WaypointShip {
 
 
 
+// Rotates (x, y) clockwise. Whole quarter turns are done by swapping coordinates so they stay
+// exact; other angles go through f64 sin/cos and carry its rounding error.
+fn rotate_clockwise(x: f64, y: f64, degrees: isize) -> (f64, f64) {
+    if degrees % 90 == 0 {
+        match (degrees / 90).rem_euclid(4) {
+            0 => (x, y),
+            1 => (y, -x),
+            2 => (-x, -y),
+            _ => (-y, x),
+        }
+    } else {
+        let (sin, cos) = (-(degrees as f64)).to_radians().sin_cos();
+
+        (x * cos - y * sin, x * sin + y * cos)
+    }
+}
+
+// The unit step for a compass bearing in whole degrees (0 is north, 90 east). Only the angle
+// within its quadrant goes through sin/cos, so quarter turns are exact and opposite bearings
+// give exactly opposite steps.
+fn unit_vector(bearing: isize) -> (f64, f64) {
+    let within = bearing.rem_euclid(90);
+    let (sin, cos) = if within == 0 { (0.0, 1.0) } else { (within as f64).to_radians().sin_cos() };
+
+    match bearing.div_euclid(90).rem_euclid(4) {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Steering {
+    // a bearing in whole degrees, kept in 0..360 so turning never loses precision
+    Heading(isize),
+    // waypoint moves add to the rotated vector, so this one does accumulate rounding error
+    // after turns that aren't quarter turns
+    Waypoint(f64, f64),
+}
+
+// A ship that can turn by any angle. It either sails along a heading, like `Ship`, or towards a
+// waypoint, like `WaypointShip`. Positions are f64; `Navigable` sees them rounded to the nearest
+// unit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ContinuousShip {
+    pub pos_x: f64,
+    pub pos_y: f64,
+    pub steering: Steering,
+}
+
+impl ContinuousShip {
+    pub fn with_heading(dir: Dir) -> ContinuousShip {
+        let bearing = match dir {
+            Dir::N => 0,
+            Dir::E => 90,
+            Dir::S => 180,
+            Dir::W => 270,
+        };
+
+        ContinuousShip { pos_x: 0.0, pos_y: 0.0, steering: Steering::Heading(bearing) }
+    }
+
+    pub fn with_waypoint(waypoint_x: isize, waypoint_y: isize) -> ContinuousShip {
+        ContinuousShip {
+            pos_x: 0.0,
+            pos_y: 0.0,
+            steering: Steering::Waypoint(waypoint_x as f64, waypoint_y as f64),
+        }
+    }
+
+    fn shift(&self, dx: isize, dy: isize) -> ContinuousShip {
+        let (dx, dy) = (dx as f64, dy as f64);
+
+        match self.steering {
+            Steering::Waypoint(x, y) => ContinuousShip { steering: Steering::Waypoint(x + dx, y + dy), ..*self },
+            Steering::Heading(_) => ContinuousShip { pos_x: self.pos_x + dx, pos_y: self.pos_y + dy, ..*self },
+        }
+    }
+
+    pub fn exact_manhattan_dist(&self, other: &ContinuousShip) -> f64 {
+        (other.pos_x - self.pos_x).abs() + (other.pos_y - self.pos_y).abs()
+    }
+}
+
+impl Navigable for ContinuousShip {
+    fn step(&self, instr: &Instr) -> ContinuousShip {
+        match *instr {
+            Instr::N(dist) => self.shift(0, dist),
+            Instr::S(dist) => self.shift(0, -dist),
+            Instr::E(dist) => self.shift(dist, 0),
+            Instr::W(dist) => self.shift(-dist, 0),
+            Instr::F(times) => {
+                let (dx, dy) = match self.steering {
+                    Steering::Heading(bearing) => unit_vector(bearing),
+                    Steering::Waypoint(x, y) => (x, y),
+                };
+
+                ContinuousShip {
+                    pos_x: self.pos_x + dx * times as f64,
+                    pos_y: self.pos_y + dy * times as f64,
+                    ..*self
+                }
+            }
+            Instr::L(angle) | Instr::R(angle) => {
+                let clockwise = if let Instr::L(_) = instr { -angle } else { angle };
+
+                let steering = match self.steering {
+                    Steering::Heading(bearing) => Steering::Heading((bearing + clockwise).rem_euclid(360)),
+                    Steering::Waypoint(x, y) => {
+                        let (x, y) = rotate_clockwise(x, y, clockwise);
+
+                        Steering::Waypoint(x, y)
+                    }
+                };
+
+                ContinuousShip { steering, ..*self }
+            }
+        }
+    }
+
+    fn position(&self) -> (isize, isize) {
+        (self.pos_x.round() as isize, self.pos_y.round() as isize)
+    }
+
+    fn waypoint(&self) -> Option<(isize, isize)> {
+        match self.steering {
+            Steering::Waypoint(x, y) => Some((x.round() as isize, y.round() as isize)),
+            Steering::Heading(_) => None,
+        }
+    }
+
+    fn manhattan_dist(&self, other: &ContinuousShip) -> usize {
+        self.exact_manhattan_dist(other).round() as usize
+    }
+}
+
 #[aoc(day12, part2)]
 fn waypoint_manhattan_dist(instrs: &Vec<Instr>) -> usize {
     let ship = WaypointShip::new(10, 1);
@@ -1,15 +1,26 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use num_bigint::{BigInt, BigUint};
+use num_integer::{ExtendedGcd, Integer};
+use num_traits::{One, Zero};
 
-type Timestamp = usize;
-type BusId = usize;
+pub type Timestamp = usize;
+pub type BusId = usize;
 
+// Departures "after" a time never include that exact minute, as in part 1: a bus leaving just
+// as you arrive at the stop has already gone.
 #[derive(Debug, Clone)]
-struct Schedule {
+pub struct Schedule {
     timestamp: Timestamp,
     bus_ids: Vec<BusId>
 }
 
 impl Schedule {
+    pub fn new(timestamp: Timestamp, bus_ids: Vec<BusId>) -> Schedule {
+        assert!(!bus_ids.contains(&0), "bus IDs must be positive");
+
+        Schedule { timestamp, bus_ids }
+    }
+
     fn next_departure_times(&self) -> Vec<(BusId, Timestamp)> {
         self.bus_ids.iter().map(|&bus_id| {
             (bus_id, (self.timestamp / bus_id + 1) * bus_id)
@@ -25,21 +36,34 @@ impl Schedule {
 
         departure_times.first().unwrap().clone()
     }
+
+    // every (time, bus) departure after `after` and no later than `until`, earliest first
+    pub fn departures_between(&self, after: Timestamp, until: Timestamp) -> Vec<(Timestamp, BusId)> {
+        let mut departures: Vec<(Timestamp, BusId)> = self.bus_ids.iter().flat_map(|&bus_id| {
+            let first = (after / bus_id + 1) * bus_id;
+
+            (first..=until).step_by(bus_id).map(move |time| (time, bus_id))
+        }).collect();
+
+        departures.sort();
+
+        departures
+    }
+
+    // the first time after the schedule's timestamp when all of `buses` leave at once; with
+    // every offset 0 the buses always line up, every lcm of their IDs
+    pub fn next_departure_together(&self, buses: &[BusId]) -> BigUint {
+        PositionedSchedule::new(buses.iter().map(|&bus_id| (0, bus_id)).collect())
+            .earliest_aligned_at_or_after(self.timestamp + 1)
+            .unwrap()
+    }
 }
 
 #[aoc_generator(day13, part1)]
-fn parse_schedule(input: &str) -> Schedule {
+pub fn parse_schedule(input: &str) -> Schedule {
     let lines: Vec<&str> = input.lines().collect();
 
-    Schedule {
-        timestamp: lines[0].parse().unwrap(),
-        bus_ids: lines[1].split(",").filter_map(|val| {
-            match val {
-                "x" => None,
-                _ => val.parse().ok()
-            }
-        }).collect()
-    }
+    Schedule::new(lines[0].parse().unwrap(), parse_schedule_with_positions(input).bus_ids())
 }
 
 #[aoc(day13, part1)]
@@ -50,40 +74,122 @@ fn bus_id_times_minutes(schedule: &Schedule) -> usize {
     bus_id * (next_timestamp - schedule.timestamp)
 }
 
+// each bus has to leave `offset` minutes after the aligned timestamp
 #[derive(Debug, Clone)]
-struct PositionedSchedule {
+pub struct PositionedSchedule {
     bus_ids_with_offsets: Vec<(usize, BusId)>
 }
 
-#[aoc_generator(day13, part2)]
-fn parse_schedule_with_positions(input: &str) -> PositionedSchedule {
-    let lines: Vec<&str> = input.lines().collect();
+impl PositionedSchedule {
+    pub fn new(bus_ids_with_offsets: Vec<(usize, BusId)>) -> PositionedSchedule {
+        assert!(bus_ids_with_offsets.iter().all(|&(_, bus)| bus != 0), "bus IDs must be positive");
 
-    PositionedSchedule {
-        bus_ids_with_offsets: lines[1].split(",").enumerate().filter_map(|val| {
-            match val {
-                (_, "x") => None,
-                (i, val) => Some((i, val.parse().unwrap()))
+        PositionedSchedule { bus_ids_with_offsets }
+    }
+
+    pub fn bus_ids(&self) -> Vec<BusId> {
+        self.bus_ids_with_offsets.iter().map(|&(_, bus)| bus).collect()
+    }
+
+    // the same constraints, but only for the given buses
+    pub fn restricted_to(&self, buses: &[BusId]) -> PositionedSchedule {
+        PositionedSchedule {
+            bus_ids_with_offsets: self.bus_ids_with_offsets.iter().filter(|(_, bus)| {
+                buses.contains(bus)
+            }).cloned().collect()
+        }
+    }
+
+    // Generalised CRT: each bus adds t = -offset (mod bus), folded into one congruence
+    // t = first (mod period). Bus IDs don't need to be coprime, and a bus that can't agree with
+    // the ones before it is reported along with one of them that it conflicts with.
+    pub fn solve(&self) -> Alignment {
+        let mut first = BigInt::zero();
+        let mut period = BigInt::one();
+
+        for (i, &(offset, bus)) in self.bus_ids_with_offsets.iter().enumerate() {
+            let residue = (-BigInt::from(offset)).mod_floor(&BigInt::from(bus));
+
+            match combine(&first, &period, &residue, &BigInt::from(bus)) {
+                Some((new_first, new_period)) => {
+                    first = new_first;
+                    period = new_period;
+                }
+                None => {
+                    // a system of congruences is solvable iff every pair of them is
+                    let conflicting = self.bus_ids_with_offsets[..i].iter().find(|&&(other_offset, other_bus)| {
+                        let gcd = bus.gcd(&other_bus);
+
+                        offset % gcd != other_offset % gcd
+                    }).cloned().unwrap();
+
+                    return Alignment::Never { first_bus: conflicting, second_bus: (offset, bus) };
+                }
             }
-        }).collect()
+        }
+
+        Alignment::Aligned { first: first.to_biguint().unwrap(), period: period.to_biguint().unwrap() }
+    }
+
+    pub fn earliest_aligned_at_or_after(&self, time: Timestamp) -> Option<BigUint> {
+        match self.solve() {
+            Alignment::Aligned { first, period } => {
+                let time = BigUint::from(time);
+
+                if time <= first {
+                    Some(first)
+                } else {
+                    Some(&first + (time - &first).div_ceil(&period) * &period)
+                }
+            }
+            Alignment::Never { .. } => None
+        }
     }
 }
 
-// Got tired and looked up the solution to this one, so it looks a lot like
-// https://gist.github.com/jacobchrismarsh/485253dbd42da10ad92d6cc03559cd84#file-day13-rs-L39
-#[aoc(day13, part2)]
-fn next_timestamp_aligned(schedule: &PositionedSchedule) -> usize {
-    let mut buses: Vec<(usize, BusId)> = schedule.bus_ids_with_offsets.clone();
-    buses.sort_by_key(|&(_, bus)| bus);
-
-    let mut possible_solution = 0;
-    let mut least_common_denominator = 1;
-    buses.iter().for_each(|(offset, bus)| {
-        while (possible_solution + offset) % bus != 0 {
-            possible_solution += least_common_denominator;
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Alignment {
+    // aligned at `first`, then again every `period` minutes
+    Aligned { first: BigUint, period: BigUint },
+    // two (offset, bus) pairs that can never both be satisfied
+    Never { first_bus: (usize, BusId), second_bus: (usize, BusId) },
+}
+
+// merges t = a1 (mod n1) and t = a2 (mod n2) into a single congruence, if they agree
+fn combine(a1: &BigInt, n1: &BigInt, a2: &BigInt, n2: &BigInt) -> Option<(BigInt, BigInt)> {
+    let ExtendedGcd { gcd, x, .. } = n1.extended_gcd(n2);
+    let diff = a2 - a1;
+
+    if !diff.is_multiple_of(&gcd) {
+        return None;
+    }
+
+    let lcm = n1 / &gcd * n2;
+    let k = (x * (diff / &gcd)).mod_floor(&(n2 / &gcd));
+
+    Some(((a1 + n1 * k).mod_floor(&lcm), lcm))
+}
+
+#[aoc_generator(day13, part2)]
+pub fn parse_schedule_with_positions(input: &str) -> PositionedSchedule {
+    let lines: Vec<&str> = input.lines().collect();
+
+    PositionedSchedule::new(lines[1].split(",").enumerate().filter_map(|val| {
+        match val {
+            (_, "x") => None,
+            (i, val) => match val.parse().unwrap() {
+                0 => panic!("bus at position {} has ID 0", i),
+                bus_id => Some((i, bus_id))
+            }
         }
-        least_common_denominator *= bus;
-    });
+    }).collect())
+}
 
-    possible_solution
+#[aoc(day13, part2)]
+fn next_timestamp_aligned(schedule: &PositionedSchedule) -> BigUint {
+    match schedule.solve() {
+        Alignment::Aligned { first, .. } => first,
+        Alignment::Never { first_bus: (o1, b1), second_bus: (o2, b2) } =>
+            panic!("bus {} at +{} and bus {} at +{} never line up", b1, o1, b2, o2)
+    }
 }
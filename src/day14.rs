@@ -2,178 +2,298 @@ use aoc_runner_derive::{aoc, aoc_generator};
 use std::str::FromStr;
 use regex::Regex;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
 
-#[derive(Debug, Copy, Clone)]
-enum MaskValue {
-    Zero,
-    One
+const DEFAULT_WIDTH: u32 = 36;
+const MAX_WIDTH: u32 = 64;
+
+// A mask as bit words: `and` has every bit that isn't forced to 0, `or` the bits forced to 1,
+// and `floating` the X bits. Bits at or above `width` are never set in any of them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Mask {
+    pub and: u64,
+    pub or: u64,
+    pub floating: u64,
+    pub width: u32,
 }
 
-type Mask = Vec<Option<MaskValue>>;
+impl Mask {
+    fn parse(s: &str) -> Option<Mask> {
+        let width = s.len() as u32;
+
+        if width == 0 || width > MAX_WIDTH {
+            return None;
+        }
+
+        let mut mask = Mask { and: 0, or: 0, floating: 0, width };
+
+        for (i, char) in s.chars().rev().enumerate() {
+            let bit = 1u64 << i;
 
-fn parse_mask(s: &str) -> Mask {
-    s.chars().map(|char| {
-        match char {
-            '0' => Some(MaskValue::Zero),
-            '1' => Some(MaskValue::One),
-            'X' => None,
-            _ => unreachable!("couldn't parse mask")
+            match char {
+                '0' => {}
+                '1' => { mask.and |= bit; mask.or |= bit; }
+                'X' => { mask.and |= bit; mask.floating |= bit; }
+                _ => return None
+            }
         }
-    }).collect()
+
+        Some(mask)
+    }
+
+    fn width_bits(&self) -> u64 {
+        u64::MAX >> (MAX_WIDTH - self.width)
+    }
+
+    // version 1: X keeps the value's bit, 0 and 1 overwrite it
+    pub fn masked_value(&self, value: u64) -> u64 {
+        (value & self.and) | self.or
+    }
+
+    // version 2: 1 sets the address bit, X floats, 0 leaves it alone
+    pub fn address_pattern(&self, address: u64) -> AddressPattern {
+        AddressPattern {
+            bits: (address & self.width_bits() & !self.floating) | self.or,
+            floating: self.floating,
+        }
+    }
+}
+
+// Every address that matches `bits` outside the `floating` positions (which are zero in `bits`).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct AddressPattern {
+    pub bits: u64,
+    pub floating: u64,
+}
+
+impl AddressPattern {
+    pub fn address_count(&self) -> u128 {
+        1u128 << self.floating.count_ones()
+    }
+
+    pub fn intersects(&self, other: &AddressPattern) -> bool {
+        (self.bits ^ other.bits) & !self.floating & !other.floating == 0
+    }
+
+    // Splits `self` into disjoint patterns covering exactly the addresses not in `other`: peel
+    // off one half for each bit that floats here but is fixed in `other`, keeping the half that
+    // disagrees with `other` and narrowing towards it on the rest.
+    pub fn subtract(&self, other: &AddressPattern) -> Vec<AddressPattern> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+
+        let mut pieces = vec![];
+        let mut rest = *self;
+        let mut split_bits = self.floating & !other.floating;
+
+        while split_bits != 0 {
+            let bit = split_bits & split_bits.wrapping_neg();
+            split_bits &= !bit;
+
+            let floating = rest.floating & !bit;
+
+            pieces.push(AddressPattern { bits: rest.bits | (!other.bits & bit), floating });
+            rest = AddressPattern { bits: rest.bits | (other.bits & bit), floating };
+        }
+
+        pieces
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = u64> {
+        let AddressPattern { bits, floating } = *self;
+
+        // walk every subset of the floating bits, finishing after the full set
+        let mut next = Some(0u64);
+
+        std::iter::from_fn(move || {
+            let subset = next?;
+            next = if subset == floating { None } else { Some(subset.wrapping_sub(floating) & floating) };
+
+            Some(bits | subset)
+        })
+    }
+
+    fn to_string(self, width: u32) -> String {
+        (0..width).rev().map(|i| {
+            match (self.floating >> i & 1, self.bits >> i & 1) {
+                (1, _) => 'X',
+                (_, 1) => '1',
+                _ => '0',
+            }
+        }).collect()
+    }
 }
 
 #[derive(Debug, Clone)]
-enum Command {
+pub enum Command {
     SetMask(Mask),
-    SetMemory { dest: usize, value: usize }
+    SetMemory { dest: u64, value: u64 }
 }
 
 #[derive(Debug, Copy, Clone)]
-struct CommandParseError;
+pub struct CommandParseError;
 
 impl FromStr for Command {
     type Err = CommandParseError;
 
     fn from_str(s: &str) -> Result<Command, Self::Err> {
-        let mask_re = Regex::new(r"mask = (?P<mask>[1|0|X]{36})").unwrap();
-        let set_mem_re = Regex::new(r"mem\[(?P<dest>\d+)\] = (?P<val>\d+)").unwrap();
+        let mask_re = Regex::new(r"^mask = (?P<mask>[01X]+)$").unwrap();
+        let set_mem_re = Regex::new(r"^mem\[(?P<dest>\d+)\] = (?P<val>\d+)$").unwrap();
 
-        let maybe_mask = mask_re.captures(s).and_then(|cap| {
-            cap.name("mask").map(|mask| parse_mask(mask.as_str()))
-        });
+        if let Some(cap) = mask_re.captures(s) {
+            return Mask::parse(&cap["mask"]).map(Command::SetMask).ok_or(CommandParseError);
+        }
 
-        maybe_mask.map(|mask| Command::SetMask(mask)).or_else(|| {
-            set_mem_re.captures(s).and_then(|cap| {
-                let dest: usize = cap.name("dest").map(|dest| dest.as_str().parse().unwrap()).unwrap();
-                let value: usize = cap.name("val").map(|val| val.as_str().parse().unwrap()).unwrap();
+        set_mem_re.captures(s).and_then(|cap| {
+            let dest = cap["dest"].parse().ok()?;
+            let value = cap["val"].parse().ok()?;
 
-                Some(Command::SetMemory { dest, value })
-            })
+            Some(Command::SetMemory { dest, value })
         }).ok_or(CommandParseError)
     }
 }
 
 #[aoc_generator(day14)]
-fn parse_commands(input: &str) -> Vec<Command> {
+pub fn parse_commands(input: &str) -> Vec<Command> {
     input.lines().map(|line| line.parse().unwrap()).collect()
 }
 
-fn masked_value(mask: &Mask, value: &usize) -> usize {
-    let mut output: usize = 0;
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DecoderVersion {
+    V1,
+    V2,
+}
 
-    for (i, mask_value) in mask.iter().rev().enumerate() {
-        match (mask_value, (value >> i) & 1)  {
-            (Some(MaskValue::One), _) => {
-                output += 1 << i;
-            }
-            (Some(MaskValue::Zero), _) => {}
-            (None, bit_val) => {
-                output += bit_val << i;
-            }
-        }
-    };
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EmulatorError {
+    NoMask,
+    WidthMismatch { expected: u32, found: u32 },
+}
 
-    output
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::NoMask => write!(f, "memory written before any mask was set"),
+            EmulatorError::WidthMismatch { expected, found } =>
+                write!(f, "{} bit mask on a {} bit emulator", found, expected),
+        }
+    }
 }
 
-fn sum_memory(memory: &HashMap<usize, usize>) -> usize {
-    memory.values().sum()
+// Version 1 writes single addresses, so memory is a plain map. Version 2 writes whole patterns,
+// kept as disjoint (pattern, value) pairs: each write is carved out of the earlier patterns
+// before being added, so the cost depends on the writes rather than on 2^(floating bits).
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    width: u32,
+    version: DecoderVersion,
+    mask: Option<Mask>,
+    memory: HashMap<u64, u64>,
+    patterns: Vec<(AddressPattern, u64)>,
 }
 
-#[aoc(day14, part1)]
-fn sum_initialized_memory(commands: &Vec<Command>) -> usize {
-    let mut mask: Option<Mask> = None;
-    let mut memory: HashMap<usize, usize> = HashMap::new();
+impl Emulator {
+    pub fn new(width: u32, version: DecoderVersion) -> Emulator {
+        assert!(0 < width && width <= MAX_WIDTH, "width must be between 1 and {} bits", MAX_WIDTH);
+
+        Emulator { width, version, mask: None, memory: HashMap::new(), patterns: vec![] }
+    }
 
-    for command in commands {
+    pub fn execute(&mut self, command: &Command) -> Result<(), EmulatorError> {
         match command {
-            Command::SetMask(new_mask) => { mask = Some(new_mask.to_vec()) },
-            Command::SetMemory {dest, value} => {
-                memory.insert(*dest, masked_value(mask.as_ref().unwrap(), value));
+            Command::SetMask(mask) if mask.width != self.width => {
+                Err(EmulatorError::WidthMismatch { expected: self.width, found: mask.width })
             }
-        }
-    };
+            Command::SetMask(mask) => {
+                self.mask = Some(*mask);
 
-    sum_memory(&memory)
-}
+                Ok(())
+            }
+            Command::SetMemory { dest, value } => {
+                let mask = self.mask.ok_or(EmulatorError::NoMask)?;
 
-enum FloatingAddressBit {
-    Zero,
-    One,
-    Floating
-}
+                match self.version {
+                    // addresses wrap to the emulator's width in both versions
+                    DecoderVersion::V1 => {
+                        self.memory.insert(*dest & mask.width_bits(), mask.masked_value(*value));
+                    }
+                    DecoderVersion::V2 => {
+                        let written = mask.address_pattern(*dest);
 
-// reversed
-type FloatingAddress = Vec<FloatingAddressBit>;
-
-fn address_mask_to_floating(mask: &Mask, address: &usize) -> FloatingAddress {
-    mask.iter().rev().enumerate().map(|(i, mask_value)| {
-        match (mask_value, (address >> i) & 1) {
-            (None, _) => FloatingAddressBit::Floating,
-            (Some(MaskValue::One), _) => FloatingAddressBit::One,
-            (Some(MaskValue::Zero), 0) => FloatingAddressBit::Zero,
-            (Some(MaskValue::Zero), 1) => FloatingAddressBit::One,
-            _ => unreachable!("unexpected bit")
-        }
-    }).collect()
-}
+                        self.patterns = self.patterns.iter().flat_map(|&(pattern, old_value)| {
+                            pattern.subtract(&written).into_iter().map(move |piece| (piece, old_value))
+                        }).collect();
 
-fn all_addresses(floating_addr: &[FloatingAddressBit]) -> Vec<usize> {
-    match floating_addr {
-        [bit] => {
-            match bit {
-                FloatingAddressBit::Floating => vec![0, 1],
-                FloatingAddressBit::Zero => vec![0],
-                FloatingAddressBit::One => vec![1]
-            }
-        },
-        [bit, rest @ ..] => {
-            match bit {
-                FloatingAddressBit::Floating => {
-                    let all = all_addresses(rest);
-
-                    all.iter().map(|addr| {
-                        let shifted = addr << 1;
-                        vec![shifted, shifted + 1]
-                    }).flatten().collect()
-                },
-                FloatingAddressBit::Zero => {
-                    let all = all_addresses(rest);
-
-                    all.iter().map(|addr| addr << 1).collect()
-                },
-                FloatingAddressBit::One => {
-                    let all = all_addresses(rest);
-
-                    all.iter().map(|addr| (addr << 1) + 1).collect()
+                        self.patterns.push((written, *value & mask.width_bits()));
+                    }
                 }
+
+                Ok(())
             }
-        },
-        _ => unreachable!("cannot compute address")
+        }
     }
-}
 
-fn mask_to_all_addresses(mask: &Mask, address: &usize) -> Vec<usize> {
-    let floating_addr = address_mask_to_floating(mask, address);
+    pub fn run(&mut self, commands: &[Command]) -> Result<(), EmulatorError> {
+        commands.iter().try_for_each(|command| self.execute(command))
+    }
 
-    all_addresses(&floating_addr[..])
-}
+    pub fn memory_sum(&self) -> u128 {
+        self.memory.values().map(|&value| value as u128).sum::<u128>()
+            + self.patterns.iter().map(|(pattern, value)| pattern.address_count() * *value as u128).sum::<u128>()
+    }
 
-#[aoc(day14, part2)]
-fn sum_initialized_memory_2(commands: &Vec<Command>) -> usize {
-    let mut mask: Option<Mask> = None;
-    let mut memory: HashMap<usize, usize> = HashMap::new();
+    // every written address and its value, lowest address first; for version 2 this expands
+    // every pattern, so `write_pattern_listing` is the better fit when many bits float
+    pub fn dump(&self) -> Vec<(u64, u64)> {
+        let mut cells: Vec<(u64, u64)> = self.memory.iter().map(|(&a, &v)| (a, v)).collect();
 
-    for command in commands {
-        match command {
-            Command::SetMask(new_mask) => { mask = Some(new_mask.to_vec()) },
-            Command::SetMemory {dest, value} => {
-                for dest in mask_to_all_addresses(mask.as_ref().unwrap(), dest) {
-                    memory.insert(dest, *value);
-                };
-            }
+        for (pattern, value) in self.patterns.iter() {
+            cells.extend(pattern.addresses().map(|address| (address, *value)));
         }
-    };
 
-    sum_memory(&memory)
+        cells.sort();
+
+        cells
+    }
+
+    pub fn write_pattern_listing<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut patterns = self.patterns.clone();
+        patterns.sort_by_key(|(pattern, _)| (pattern.bits, pattern.floating));
+
+        for (pattern, value) in patterns {
+            writeln!(out, "{}: {}", pattern.to_string(self.width), value)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_hex_listing<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let digits = self.width.div_ceil(4) as usize;
+
+        for (address, value) in self.dump() {
+            writeln!(out, "{:0w$x}: {:0w$x}", address, value, w = digits)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn run_emulator(commands: &[Command], version: DecoderVersion) -> u128 {
+    let mut emulator = Emulator::new(DEFAULT_WIDTH, version);
+
+    emulator.run(commands).unwrap_or_else(|err| panic!("{}", err));
+
+    emulator.memory_sum()
+}
+
+#[aoc(day14, part1)]
+fn sum_initialized_memory(commands: &Vec<Command>) -> u128 {
+    run_emulator(commands, DecoderVersion::V1)
+}
+
+#[aoc(day14, part2)]
+fn sum_initialized_memory_2(commands: &Vec<Command>) -> u128 {
+    run_emulator(commands, DecoderVersion::V2)
 }
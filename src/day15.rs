@@ -1,39 +1,192 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
 
 #[aoc_generator(day15)]
 fn parse_input(input: &str) -> Vec<usize> {
-    input.split(",").map(|n| n.parse().unwrap()).collect()
+    input.trim().split(",").map(|n| n.parse().unwrap()).collect()
 }
 
-fn value_at_iteration(input: &Vec<usize>, iteration: usize) -> usize {
-    let mut previous_turns: HashMap<usize, Vec<usize>> = HashMap::new();
-    let mut last_value: Option<usize> = None;
+// Plays the game one turn per `next`, forever once the starting numbers run out. `last_seen[n]`
+// is the turn n was spoken before the most recent turn (0 if never), so the previous number is
+// only recorded after its age has been read off. Turns are stored as u32 to halve the table,
+// which is as long as the game; a game past u32::MAX turns panics rather than wrapping.
+#[derive(Debug, Clone)]
+pub struct MemoryGame {
+    starting: Vec<usize>,
+    last_seen: Vec<u32>,
+    turn: usize,
+    last: Option<usize>,
+}
 
-    for i in 1..=iteration {
-        last_value = if let Some(&v) = input.get(i - 1) {
-            Some(v)
-        } else {
-            let prev_turns =
-                previous_turns.entry(last_value.unwrap()).or_insert(vec![]).as_slice();
-            Some(match prev_turns {
-                [] | [_] => 0,
-                [.., prev_turn, last_turn] => last_turn - prev_turn,
-            })
+impl MemoryGame {
+    pub fn new(starting: &[usize]) -> MemoryGame {
+        MemoryGame::with_capacity(starting, 0)
+    }
+
+    // every number spoken after the starting list is younger than the turn it's spoken on, so
+    // reserving `turns` slots means the table never grows for a game of that length
+    pub fn with_capacity(starting: &[usize], turns: usize) -> MemoryGame {
+        let size = starting.iter().map(|&n| n + 1).max().unwrap_or(0).max(turns);
+
+        MemoryGame { starting: starting.to_vec(), last_seen: vec![0; size], turn: 0, last: None }
+    }
+
+    // the turn most recently played, 0 before the game starts
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    pub fn last_spoken(&self) -> Option<usize> {
+        self.last
+    }
+
+    // Plays up to and including `turn`, calling `on_progress(turn, spoken)` every `report_every`
+    // turns (never if 0). Returns the number spoken on `turn`, or `None` if it's already past or
+    // there were no starting numbers.
+    pub fn play_to<F: FnMut(usize, usize)>(&mut self, turn: usize, report_every: usize, mut on_progress: F) -> Option<usize> {
+        if turn < self.turn {
+            return None;
+        }
+
+        while self.turn < turn {
+            let spoken = self.next()?;
+
+            if report_every != 0 && self.turn.is_multiple_of(report_every) {
+                on_progress(self.turn, spoken);
+            }
+        }
+
+        self.last
+    }
+}
+
+impl Iterator for MemoryGame {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let spoken = match (self.starting.get(self.turn), self.last) {
+            (Some(&n), _) => n,
+            (None, Some(last)) => match self.last_seen[last] {
+                0 => 0,
+                seen => self.turn - seen as usize,
+            },
+            (None, None) => return None,
         };
 
-        previous_turns.entry(last_value.unwrap()).or_insert(vec![]).push(i);
+        if let Some(last) = self.last {
+            self.last_seen[last] = u32::try_from(self.turn).expect("memory game ran past u32::MAX turns");
+        }
+
+        if spoken >= self.last_seen.len() {
+            self.last_seen.resize(spoken + 1, 0);
+        }
+
+        self.turn += 1;
+        self.last = Some(spoken);
+
+        Some(spoken)
+    }
+}
+
+pub fn value_at_turn(starting: &[usize], turn: usize) -> Option<usize> {
+    MemoryGame::with_capacity(starting, turn).play_to(turn, 0, |_, _| {})
+}
+
+// the longest wait between two turns speaking the same number
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Gap {
+    pub number: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl Gap {
+    pub fn turns(&self) -> usize {
+        self.to - self.from
+    }
+}
+
+// Per-number tables indexed like `MemoryGame::last_seen`, with 0 meaning never spoken.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceStats {
+    turns: usize,
+    first_turn: Vec<usize>,
+    last_turn: Vec<usize>,
+    counts: Vec<usize>,
+    longest_gap: Option<Gap>,
+}
+
+impl SequenceStats {
+    fn record(&mut self, spoken: usize) {
+        self.turns += 1;
+
+        if spoken >= self.counts.len() {
+            self.first_turn.resize(spoken + 1, 0);
+            self.last_turn.resize(spoken + 1, 0);
+            self.counts.resize(spoken + 1, 0);
+        }
+
+        if self.first_turn[spoken] == 0 {
+            self.first_turn[spoken] = self.turns;
+        } else {
+            let gap = Gap { number: spoken, from: self.last_turn[spoken], to: self.turns };
+
+            if self.longest_gap.is_none_or(|longest| gap.turns() > longest.turns()) {
+                self.longest_gap = Some(gap);
+            }
+        }
+
+        self.last_turn[spoken] = self.turns;
+        self.counts[spoken] += 1;
+    }
+
+    pub fn turns(&self) -> usize {
+        self.turns
+    }
+
+    pub fn first_turn(&self, number: usize) -> Option<usize> {
+        self.first_turn.get(number).copied().filter(|&turn| turn != 0)
+    }
+
+    pub fn frequency(&self, number: usize) -> usize {
+        self.counts.get(number).copied().unwrap_or(0)
+    }
+
+    // how often each number was spoken, leaving out the ones that never were
+    pub fn histogram(&self) -> BTreeMap<usize, usize> {
+        self.counts.iter().enumerate().filter(|(_, &count)| count != 0).map(|(n, &count)| (n, count)).collect()
+    }
+
+    // the earliest of the longest gaps, if any number was spoken twice
+    pub fn longest_gap(&self) -> Option<Gap> {
+        self.longest_gap
+    }
+}
+
+pub fn analyse(starting: &[usize], turns: usize) -> SequenceStats {
+    analyse_and_export(starting, turns, &mut io::sink()).unwrap()
+}
+
+// same as `analyse`, also writing each term on its own line to `out` during the single pass
+pub fn analyse_and_export<W: Write>(starting: &[usize], turns: usize, out: &mut W) -> io::Result<SequenceStats> {
+    let mut stats = SequenceStats::default();
+
+    for spoken in MemoryGame::with_capacity(starting, turns).take(turns) {
+        writeln!(out, "{}", spoken)?;
+        stats.record(spoken);
     }
 
-    last_value.unwrap()
+    Ok(stats)
 }
 
 #[aoc(day15, part1)]
 fn iteration_2020_number(input: &Vec<usize>) -> usize {
-    value_at_iteration(input, 2020)
+    value_at_turn(input, 2020).unwrap()
 }
 
 #[aoc(day15, part2)]
 fn iteration_30000000_number(input: &Vec<usize>) -> usize {
-    value_at_iteration(input, 30000000)
+    value_at_turn(input, 30000000).unwrap()
 }
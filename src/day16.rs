@@ -1,48 +1,166 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use parse_display::{Display, FromStr};
-use std::collections::{HashMap, HashSet};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::io::{self, Write};
 
 #[derive(Debug, FromStr, Display, Copy, Clone, Eq, PartialEq, Hash)]
 #[display("{min}-{max}")]
-struct Range {
-    min: usize,
-    max: usize
+pub struct Range {
+    pub min: usize,
+    pub max: usize
 }
 
-impl Range {
-    fn is_within(&self, value: usize) -> bool {
-        self.min <= value && value <= self.max
+// Sorted, disjoint inclusive ranges with a gap of at least one value between neighbours, so
+// lookups are a binary search on `max`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct IntervalSet {
+    ranges: Vec<Range>,
+}
+
+impl IntervalSet {
+    pub fn new() -> IntervalSet {
+        IntervalSet::default()
+    }
+
+    pub fn from_ranges<I: IntoIterator<Item = Range>>(ranges: I) -> IntervalSet {
+        let mut sorted: Vec<Range> = ranges.into_iter().filter(|range| range.min <= range.max).collect();
+        sorted.sort_by_key(|range| range.min);
+
+        let mut merged: Vec<Range> = Vec::with_capacity(sorted.len());
+
+        for range in sorted {
+            match merged.last_mut() {
+                Some(last) if range.min <= last.max.saturating_add(1) => last.max = last.max.max(range.max),
+                _ => merged.push(range),
+            }
+        }
+
+        IntervalSet { ranges: merged }
+    }
+
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // the only range that could hold `value`: the first one not entirely below it
+    fn candidate(&self, value: usize) -> Option<&Range> {
+        self.ranges.get(self.ranges.partition_point(|range| range.max < value))
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.candidate(value).is_some_and(|range| range.min <= value)
+    }
+
+    pub fn contains_range(&self, range: &Range) -> bool {
+        self.candidate(range.min).is_some_and(|r| r.min <= range.min && range.max <= r.max)
+    }
+
+    pub fn insert(&mut self, range: Range) {
+        *self = self.union(&IntervalSet::from_ranges(vec![range]));
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        IntervalSet::from_ranges(self.ranges.iter().chain(other.ranges.iter()).copied())
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut ranges = vec![];
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (self.ranges[i], other.ranges[j]);
+            let overlap = Range { min: a.min.max(b.min), max: a.max.min(b.max) };
+
+            if overlap.min <= overlap.max {
+                ranges.push(overlap);
+            }
+
+            // whichever ends first can't overlap anything further in the other set
+            if a.max < b.max { i += 1 } else { j += 1 }
+        }
+
+        IntervalSet { ranges }
     }
 }
 
-type Ticket = Vec<usize>;
+pub type Ticket = Vec<usize>;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
-struct Rule {
-    name: String,
-    ranges: Vec<Range>
+pub struct Rule {
+    pub name: String,
+    pub ranges: IntervalSet
 }
 
 impl Rule {
     fn valid_value(&self, value: usize) -> bool {
-        self.ranges.iter().any(|range| {
-            range.is_within(value)
-        })
+        self.ranges.contains(value)
     }
 }
 
+// Fields are read-only from outside so `valid_values` can't fall out of step with `rules`.
 #[derive(Debug, Clone)]
-struct Data {
+pub struct Data {
     rules: Vec<Rule>,
     my_ticket: Ticket,
-    nearby_tickets: Vec<Ticket>
+    nearby_tickets: Vec<Ticket>,
+    // every value that fits at least one rule
+    valid_values: IntervalSet,
+}
+
+// a value on a nearby ticket that fits no rule at all
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct InvalidValue {
+    pub ticket: usize,
+    pub position: usize,
+    pub value: usize,
 }
 
 impl Data {
+    pub fn new(rules: Vec<Rule>, my_ticket: Ticket, nearby_tickets: Vec<Ticket>) -> Data {
+        let valid_values = IntervalSet::from_ranges(rules.iter().flat_map(|rule| rule.ranges.ranges().iter().copied()));
+
+        Data { rules, my_ticket, nearby_tickets, valid_values }
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn my_ticket(&self) -> &Ticket {
+        &self.my_ticket
+    }
+
+    pub fn nearby_tickets(&self) -> &[Ticket] {
+        &self.nearby_tickets
+    }
+
+    pub fn valid_values(&self) -> &IntervalSet {
+        &self.valid_values
+    }
+
     fn invalid_values_for_ticket(&self, ticket: &Ticket) -> Vec<usize> {
-        ticket.iter().filter(|&&value| {
-            self.rules.iter().all(|rule| !rule.valid_value(value))
-        }).cloned().collect()
+        ticket.iter().filter(|&&value| !self.valid_values.contains(value)).cloned().collect()
+    }
+
+    // every invalid value on the nearby tickets, in ticket then position order
+    pub fn invalid_ticket_report(&self) -> Vec<InvalidValue> {
+        self.nearby_tickets.iter().enumerate().flat_map(|(ticket, values)| {
+            values.iter().enumerate().filter(move |&(_, &value)| {
+                !self.valid_values.contains(value)
+            }).map(move |(position, &value)| InvalidValue { ticket, position, value })
+        }).collect()
+    }
+
+    // the nearby tickets without invalid values, with their index among all nearby tickets
+    fn indexed_valid_nearby_tickets(&self) -> impl Iterator<Item = (usize, &Ticket)> {
+        self.nearby_tickets.iter().enumerate().filter(move |(_, ticket)| {
+            self.invalid_values_for_ticket(ticket).is_empty()
+        })
     }
 
     fn invalid_values_for_nearby_tickets(&self) -> Vec<Vec<usize>> {
@@ -54,43 +172,193 @@ impl Data {
     }
 
     fn valid_nearby_tickets(&self) -> Vec<Ticket> {
-        self.nearby_tickets.iter().filter(|&ticket| {
-            self.invalid_values_for_ticket(ticket).len() == 0
-        }).cloned().collect()
+        self.indexed_valid_nearby_tickets().map(|(_, ticket)| ticket.clone()).collect()
     }
 
-    fn assign_field_names(&self) -> HashMap<Rule, usize> {
+    // allowed[position][rule] is whether every valid nearby ticket fits the rule at that position
+    fn allowed_rules(&self) -> Vec<Vec<bool>> {
         let tickets = self.valid_nearby_tickets();
-        let num_fields = tickets.first().unwrap().len();
-
-        let mut candidates: HashMap<usize, HashSet<Rule>> = (0..num_fields).map(|field| {
-            (field, self.rules.iter().filter(|rule| {
-                tickets.iter().all(|ticket| {
-                    rule.valid_value(ticket[field])
-                })
-            }).cloned().collect())
-        }).collect();
-
-        let mut assignments = HashMap::new();
-
-        while !candidates.is_empty() {
-            let (&field, ruleset) = candidates.iter().filter(|&(_, ruleset)| {
-                ruleset.len() == 1
-            }).next().unwrap();
-
-            let rule = ruleset.iter().next().unwrap().clone();
-            assignments.insert(rule.clone(), field);
-
-            // delete field from candidates, and delete rule from everything
-            candidates.values_mut().for_each(|ruleset| {
-                ruleset.remove(&rule);
-            });
-            candidates.remove(&field);
+
+        (0..self.my_ticket.len()).map(|position| {
+            self.rules.iter().map(|rule| {
+                tickets.iter().all(|ticket| rule.valid_value(ticket[position]))
+            }).collect()
+        }).collect()
+    }
+
+    // Matches positions to rules with augmenting paths, trying positions in order and rules in
+    // input order so the result doesn't depend on hashing. Once every position is matched, each
+    // one is unmatched in turn and re-augmented without its rule: success there means a second
+    // assignment exists.
+    pub fn assign_field_names(&self) -> Result<FieldAssignment, AssignmentError> {
+        let positions = self.my_ticket.len();
+
+        if self.rules.len() != positions {
+            return Err(AssignmentError::FieldCountMismatch { rules: self.rules.len(), positions });
+        }
+
+        let allowed = self.allowed_rules();
+        let mut matching = Matching {
+            allowed: &allowed,
+            rule_at: vec![None; positions],
+            position_of: vec![None; self.rules.len()],
+        };
+
+        for position in 0..positions {
+            if !matching.augment(position, &mut vec![false; self.rules.len()], None) {
+                return Err(AssignmentError::NoValidAssignment { position });
+            }
         }
 
-        assignments
+        let assignment = FieldAssignment {
+            fields: matching.rule_at.iter().map(|rule| self.rules[rule.unwrap()].name.clone()).collect(),
+        };
+
+        for position in 0..positions {
+            let mut alternative = matching.clone();
+            let rule = alternative.rule_at[position].take().unwrap();
+            alternative.position_of[rule] = None;
+
+            if alternative.augment(position, &mut vec![false; self.rules.len()], Some(rule)) {
+                let other = alternative.rule_at[position].unwrap();
+
+                return Err(AssignmentError::Ambiguous {
+                    assignment,
+                    position,
+                    alternative: self.rules[other].name.clone(),
+                });
+            }
+        }
+
+        Ok(assignment)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Matching<'a> {
+    allowed: &'a [Vec<bool>],
+    rule_at: Vec<Option<usize>>,
+    position_of: Vec<Option<usize>>,
+}
+
+impl<'a> Matching<'a> {
+    fn augment(&mut self, position: usize, visited: &mut [bool], forbidden: Option<usize>) -> bool {
+        for rule in 0..visited.len() {
+            if !self.allowed[position][rule] || visited[rule] || forbidden == Some(rule) {
+                continue;
+            }
+
+            visited[rule] = true;
+
+            let free = match self.position_of[rule] {
+                None => true,
+                Some(other) => self.augment(other, visited, None),
+            };
+
+            if free {
+                self.rule_at[position] = Some(rule);
+                self.position_of[rule] = Some(position);
+
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+// the field name at each ticket position
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FieldAssignment {
+    fields: Vec<String>,
+}
+
+impl FieldAssignment {
+    pub fn field_at(&self, position: usize) -> Option<&str> {
+        self.fields.get(position).map(|name| name.as_str())
+    }
+
+    pub fn position_of(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.fields.iter().map(|name| name.as_str()).enumerate()
+    }
+
+    pub fn decode(&self, ticket: &Ticket) -> Map<String, Value> {
+        self.fields.iter().zip(ticket).map(|(name, &value)| (name.clone(), json!(value))).collect()
     }
+}
+
+// `{"mine": {field: value}, "nearby": [{"ticket": index, "fields": {field: value}}]}`, with only the
+// valid nearby tickets and `ticket` being the index among all of them
+pub fn tickets_to_json(data: &Data, assignment: &FieldAssignment) -> Value {
+    let nearby: Vec<Value> = data.indexed_valid_nearby_tickets().map(|(index, ticket)| {
+        json!({ "ticket": index, "fields": assignment.decode(ticket) })
+    }).collect();
+
+    json!({ "mine": assignment.decode(data.my_ticket()), "nearby": nearby })
+}
+
+pub fn write_tickets_json<W: Write>(data: &Data, assignment: &FieldAssignment, out: &mut W) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, &tickets_to_json(data, assignment))?;
+
+    writeln!(out)
+}
 
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// one row per ticket with the fields in position order, labelled `mine` or by nearby ticket index
+pub fn write_tickets_csv<W: Write>(data: &Data, assignment: &FieldAssignment, out: &mut W) -> io::Result<()> {
+    let header: Vec<String> = assignment.iter().map(|(_, name)| csv_field(name)).collect();
+    writeln!(out, "ticket,{}", header.join(","))?;
+
+    let rows = std::iter::once(("mine".to_string(), data.my_ticket()))
+        .chain(data.indexed_valid_nearby_tickets().map(|(index, ticket)| (index.to_string(), ticket)));
+
+    for (label, ticket) in rows {
+        let values: Vec<String> = ticket.iter().map(|value| value.to_string()).collect();
+        writeln!(out, "{},{}", label, values.join(","))?;
+    }
+
+    Ok(())
+}
+
+pub fn write_invalid_report<W: Write>(data: &Data, out: &mut W) -> io::Result<()> {
+    for InvalidValue { ticket, position, value } in data.invalid_ticket_report() {
+        writeln!(out, "nearby ticket {}: {} at position {}", ticket, value, position)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AssignmentError {
+    FieldCountMismatch { rules: usize, positions: usize },
+    // no assignment covers this position along with every position before it
+    NoValidAssignment { position: usize },
+    // `assignment` is valid, but so is another with `alternative` at `position`
+    Ambiguous { assignment: FieldAssignment, position: usize, alternative: String },
+}
+
+impl fmt::Display for AssignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssignmentError::FieldCountMismatch { rules, positions } =>
+                write!(f, "{} rules for {} ticket positions", rules, positions),
+            AssignmentError::NoValidAssignment { position } =>
+                write!(f, "positions 0..={} can't all be given distinct fields", position),
+            AssignmentError::Ambiguous { assignment, position, alternative } =>
+                write!(f, "position {} could be {} or {}", position, assignment.field_at(*position).unwrap(), alternative),
+        }
+    }
 }
 
 fn parse_rules(section: &str) -> Vec<Rule> {
@@ -102,11 +370,11 @@ fn parse_rules(section: &str) -> Vec<Rule> {
 
         let ranges = ranges_text
             .split(" or ")
-            .map(|range_text| range_text.parse::<Range>().unwrap()).collect();
+            .map(|range_text| range_text.parse::<Range>().unwrap());
 
         Rule {
             name: name.to_string(),
-            ranges
+            ranges: IntervalSet::from_ranges(ranges)
         }
     }).collect()
 }
@@ -126,14 +394,14 @@ fn parse_nearby_tickets(section: &str) -> Vec<Ticket> {
 }
 
 #[aoc_generator(day16)]
-fn parse_input(input: &str) -> Data {
+pub fn parse_input(input: &str) -> Data {
     let sections: Vec<&str> = input.split("\n\n").collect();
 
-    Data {
-        rules: parse_rules(sections[0]),
-        my_ticket: parse_my_ticket(sections[1]),
-        nearby_tickets: parse_nearby_tickets(sections[2])
-    }
+    Data::new(
+        parse_rules(sections[0]),
+        parse_my_ticket(sections[1]),
+        parse_nearby_tickets(sections[2])
+    )
 }
 
 #[aoc(day16, part1)]
@@ -143,11 +411,9 @@ fn ticket_scanning_error_rate(data: &Data) -> usize {
 
 #[aoc(day16, part2)]
 fn departure_fields_product(data: &Data) -> usize {
-    let assignments = data.assign_field_names();
-
-    let fields = assignments.keys().filter(|rule| {
-        rule.name.starts_with("departure")
-    }).map(|r| assignments.get(r).unwrap());
+    let assignment = data.assign_field_names().unwrap_or_else(|err| panic!("{}", err));
 
-    fields.map(|&f| data.my_ticket[f]).product()
+    assignment.iter().filter(|(_, name)| {
+        name.starts_with("departure")
+    }).map(|(position, _)| data.my_ticket()[position]).product()
 }
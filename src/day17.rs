@@ -1,337 +1,280 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
 use std::ops::RangeInclusive;
 
-type Coord3 = (isize, isize, isize);
-type Coord4 = (isize, isize, isize, isize);
+// x and y come first, matching the input slice; any further axes start at 0
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Coord<const N: usize>(pub [isize; N]);
 
-#[derive(Debug, Copy, Clone)]
-enum State {
-    Active,
-    Inactive
-}
+impl<const N: usize> Coord<N> {
+    // the same cell in a pocket dimension with at least as many axes
+    pub fn embed<const M: usize>(&self) -> Coord<M> {
+        assert!(N <= M, "can't embed {} dimensions in {}", N, M);
 
-#[aoc_generator(day17)]
-fn parse_input(input: &str) -> HashMap<Coord3, State> {
-    let mut data = HashMap::new();
+        let mut coord = [0; M];
+        coord[..N].copy_from_slice(&self.0);
 
-    for (y, line) in input.lines().enumerate() {
-        for (x, char) in line.chars().enumerate() {
-            let value = match char {
-                '.' => State::Inactive,
-                '#' => State::Active,
-                _ => unreachable!("unrecognized input char")
-            };
-
-            data.insert((x as isize, y as isize, 0), value);
-        }
+        Coord(coord)
     }
 
-    data
-}
-
-// count of (Active, Inactive)
-fn count_neighboring_states_3(input: &HashMap<Coord3, State>, coord: &Coord3) -> (usize, usize) {
-    let &(x0, y0, z0) = coord;
+    // the 3^N - 1 cells differing by at most one along every axis
+    fn neighbours(&self) -> impl Iterator<Item = Coord<N>> + '_ {
+        (0..3usize.pow(N as u32)).filter_map(move |mut index| {
+            let mut coord = self.0;
+            let mut moved = false;
 
-    let mut actives = 0;
-    let mut inactives = 0;
+            for axis in coord.iter_mut() {
+                let offset = (index % 3) as isize - 1;
+                index /= 3;
 
-    for x in x0-1 ..= x0+1 {
-        for y in y0-1 ..= y0+1 {
-            for z in z0-1 ..= z0+1 {
-                if (x, y, z) != (x0, y0, z0) {
-                    match input.get(&(x, y, z)).unwrap_or(&State::Inactive) {
-                        State::Inactive => { inactives += 1; }
-                        State::Active => { actives += 1; }
-                    }
-                }
+                *axis += offset;
+                moved |= offset != 0;
             }
-        }
-    }
 
-    (actives, inactives)
+            if moved { Some(Coord(coord)) } else { None }
+        })
+    }
 }
 
-type CoordRange3 = (RangeInclusive<isize>, RangeInclusive<isize>, RangeInclusive<isize>);
-
-fn coord_range_3(data: &HashMap<Coord3, State>) -> CoordRange3 {
-    let mut x_min = None;
-    let mut y_min = None;
-    let mut z_min = None;
-    let mut x_max = None;
-    let mut y_max = None;
-    let mut z_max = None;
-
-    let keys: Vec<&Coord3> = data.keys().collect();
-
-    for &(x, y, z) in keys {
-        if x_min.is_none() || x < x_min.unwrap() {
-            x_min = Some(x);
-        }
-
-        if y_min.is_none() || y < y_min.unwrap() {
-            y_min = Some(y);
-        }
-
-        if z_min.is_none() || z < z_min.unwrap() {
-            z_min = Some(z);
-        }
-
-        if x_max.is_none() || x > x_max.unwrap() {
-            x_max = Some(x);
-        }
+pub type ActiveSet<const N: usize> = HashSet<Coord<N>>;
 
-        if y_max.is_none() || y > y_max.unwrap() {
-            y_max = Some(y);
-        }
+#[aoc_generator(day17)]
+fn parse_input(input: &str) -> ActiveSet<2> {
+    let mut data = HashSet::new();
 
-        if z_max.is_none() || z > z_max.unwrap() {
-            z_max = Some(z);
+    for (y, line) in input.lines().enumerate() {
+        for (x, char) in line.chars().enumerate() {
+            match char {
+                '.' => {}
+                '#' => { data.insert(Coord([x as isize, y as isize])); }
+                _ => unreachable!("unrecognized input char")
+            }
         }
     }
 
-    (
-        x_min.unwrap() - 1 ..= x_max.unwrap() + 1,
-        y_min.unwrap() - 1 ..= y_max.unwrap() + 1,
-        z_min.unwrap() - 1 ..= z_max.unwrap() + 1
-    )
+    data
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Symmetry {
+    None,
+    // The initial slice sits at 0 on every axis past y, so the state stays symmetric under
+    // negating any of them. Only cells with those coordinates >= 0 are stored.
+    MirrorExtraAxes,
 }
 
-fn step_one_cycle_3(input: &HashMap<Coord3, State>) -> HashMap<Coord3, State> {
-    let mut output = HashMap::new();
+// Only active cells are stored. Each cycle scatters a count from every active cell to its
+// neighbours, so work is proportional to the active cells rather than their bounding box.
+#[derive(Debug, Clone)]
+pub struct PocketDimension<const N: usize> {
+    active: ActiveSet<N>,
+    symmetry: Symmetry,
+    cycles: usize,
+}
 
-    let (x_range, y_range, z_range) = coord_range_3(input);
+impl<const N: usize> PocketDimension<N> {
+    pub fn new(slice: &ActiveSet<2>, symmetry: Symmetry) -> PocketDimension<N> {
+        PocketDimension { active: slice.iter().map(|coord| coord.embed()).collect(), symmetry, cycles: 0 }
+    }
 
-    for x in x_range {
-        for y in y_range.clone() {
-            for z in z_range.clone() {
-                let coord = (x, y, z);
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
 
-                let (actives, _inactives) = count_neighboring_states_3(input, &coord);
+    // With folding, a stored cell that's 1 on an extra axis also stands for its mirror at -1,
+    // which is adjacent to a neighbour at 0 on that axis too. No other mirror image is ever
+    // adjacent to a stored cell, so each such axis doubles the count and nothing else changes.
+    fn neighbour_counts(&self) -> HashMap<Coord<N>, usize> {
+        let mut counts = HashMap::new();
+
+        for cell in self.active.iter() {
+            for neighbour in cell.neighbours() {
+                let weight = match self.symmetry {
+                    Symmetry::None => 1,
+                    Symmetry::MirrorExtraAxes => {
+                        if neighbour.0[2..].iter().any(|&value| value < 0) {
+                            continue;
+                        }
 
-                let current_state = input.get(&coord).unwrap_or(&State::Inactive);
+                        let doubled = (2..N).filter(|&axis| cell.0[axis] == 1 && neighbour.0[axis] == 0).count();
 
-                match current_state {
-                    State::Active => {
-                        if actives == 2 || actives == 3 {
-                            output.insert(coord, State::Active);
-                        } else {
-                            output.insert(coord, State::Inactive);
-                        }
-                    },
-                    State::Inactive => {
-                        if actives == 3 {
-                            output.insert(coord, State::Active);
-                        } else {
-                            output.insert(coord, State::Inactive);
-                        }
+                        1 << doubled
                     }
-                }
+                };
+
+                *counts.entry(neighbour).or_insert(0) += weight;
             }
         }
+
+        counts
     }
 
-    output
-}
+    pub fn step(&mut self) {
+        let counts = self.neighbour_counts();
 
-fn step_n_cycles_3(input: &HashMap<Coord3, State>, n: usize) -> HashMap<Coord3, State> {
-    let mut output = input.clone();
+        self.active = counts.into_iter().filter(|(coord, count)| {
+            *count == 3 || (*count == 2 && self.active.contains(coord))
+        }).map(|(coord, _)| coord).collect();
 
-    for _ in 0..n {
-        output = step_one_cycle_3(&output);
+        self.cycles += 1;
     }
 
-    output.clone()
-}
+    pub fn run(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
 
-fn _print_state(data: &HashMap<Coord3, State>) {
-    let (x_range, y_range, z_range) = coord_range_3(data);
+    // every active cell, unfolding mirrored ones
+    pub fn active_cells(&self) -> ActiveSet<N> {
+        let mut cells = HashSet::new();
 
+        for &cell in self.active.iter() {
+            let mut images = vec![cell];
 
-    for z in z_range {
-        println!("z = {}", z);
+            if self.symmetry == Symmetry::MirrorExtraAxes {
+                for axis in (2..N).filter(|&axis| cell.0[axis] != 0) {
+                    let mirrored: Vec<Coord<N>> = images.iter().map(|&Coord(mut coord)| {
+                        coord[axis] = -coord[axis];
 
-        for y in y_range.clone() {
-            for x in x_range.clone() {
-                let value = data.get(&(x, y, z)).unwrap_or(&State::Inactive);
+                        Coord(coord)
+                    }).collect();
 
-                match value {
-                    State::Active => { print!("#"); }
-                    State::Inactive => { print!("."); }
+                    images.extend(mirrored);
                 }
             }
 
-            println!();
+            cells.extend(images);
         }
 
-        println!();
+        cells
     }
-}
 
-#[aoc(day17, part1)]
-fn six_cycles_active_count(input: &HashMap<Coord3, State>) -> usize {
-    let result = step_n_cycles_3(input, 6);
-
-    result.values().filter(|&&value| {
-        match value {
-            State::Active => true,
-            _ => false
+    pub fn active_count(&self) -> usize {
+        match self.symmetry {
+            Symmetry::None => self.active.len(),
+            Symmetry::MirrorExtraAxes => self.active.iter().map(|cell| {
+                1 << cell.0[2..].iter().filter(|&&value| value != 0).count()
+            }).sum(),
         }
-    }).count()
-}
-
-
-fn map_to_hypercube(input: &HashMap<Coord3, State>) -> HashMap<Coord4, State> {
-    let mut output = HashMap::new();
-
-    for (&(x,y,z), &state) in input.iter() {
-        output.insert((x,y,z,0), state);
     }
-
-    output
 }
 
-// count of (Active, Inactive)
-fn count_neighboring_states_4(input: &HashMap<Coord4, State>, coord: &Coord4) -> (usize, usize) {
-    let &(x0, y0, z0, w0) = coord;
-
-    let mut actives = 0;
-    let mut inactives = 0;
-
-    for x in x0-1 ..= x0+1 {
-        for y in y0-1 ..= y0+1 {
-            for z in z0-1 ..= z0+1 {
-                for w in w0-1 ..= w0+1 {
-                    if (x, y, z, w) != (x0, y0, z0, w0) {
-                        match input.get(&(x, y, z, w)).unwrap_or(&State::Inactive) {
-                            State::Inactive => { inactives += 1; }
-                            State::Active => { actives += 1; }
-                        }
-                    }
-                }
-            }
+// the bounding box of every active cell
+fn coord_range<const N: usize>(data: &ActiveSet<N>) -> [RangeInclusive<isize>; N] {
+    let mut min = [isize::MAX; N];
+    let mut max = [isize::MIN; N];
+
+    for Coord(coord) in data.iter() {
+        for axis in 0..N {
+            min[axis] = min[axis].min(coord[axis]);
+            max[axis] = max[axis].max(coord[axis]);
         }
     }
 
-    (actives, inactives)
+    std::array::from_fn(|axis| min[axis] ..= max[axis])
 }
 
-type CoordRange4 = (RangeInclusive<isize>, RangeInclusive<isize>, RangeInclusive<isize>, RangeInclusive<isize>);
+// every coordinate in the box, the first axis changing fastest
+fn coords_in<const N: usize>(ranges: &[RangeInclusive<isize>; N]) -> impl Iterator<Item = Coord<N>> + '_ {
+    let total: usize = ranges.iter().map(|range| range.clone().count()).product();
 
-fn coord_range_4(data: &HashMap<Coord4, State>) -> CoordRange4 {
-    let mut x_min = None;
-    let mut y_min = None;
-    let mut z_min = None;
-    let mut x_max = None;
-    let mut y_max = None;
-    let mut z_max = None;
-    let mut w_min = None;
-    let mut w_max = None;
+    (0..total).map(move |mut index| {
+        let mut coord = [0; N];
 
-    let keys: Vec<&Coord4> = data.keys().collect();
+        for (axis, range) in ranges.iter().enumerate() {
+            let len = range.clone().count();
 
-    for &(x, y, z, w) in keys {
-        if x_min.is_none() || x < x_min.unwrap() {
-            x_min = Some(x);
+            coord[axis] = range.start() + (index % len) as isize;
+            index /= len;
         }
 
-        if y_min.is_none() || y < y_min.unwrap() {
-            y_min = Some(y);
-        }
+        Coord(coord)
+    })
+}
 
-        if z_min.is_none() || z < z_min.unwrap() {
-            z_min = Some(z);
-        }
+// the initial slice embedded in N dimensions, after `cycles` cycles
+pub fn active_count_after<const N: usize>(input: &ActiveSet<2>, cycles: usize, symmetry: Symmetry) -> usize {
+    let mut pocket: PocketDimension<N> = PocketDimension::new(input, symmetry);
+    pocket.run(cycles);
 
-        if w_min.is_none() || w < w_min.unwrap() {
-            w_min = Some(w);
-        }
+    pocket.active_count()
+}
 
-        if x_max.is_none() || x > x_max.unwrap() {
-            x_max = Some(x);
-        }
+// every x/y slice of the active cells' bounding box, labelled by its other coordinates
+pub fn write_slices<const N: usize, W: Write>(pocket: &PocketDimension<N>, out: &mut W) -> io::Result<()> {
+    let data = pocket.active_cells();
+    let ranges = coord_range(&data);
+
+    for slice in coords_in(&ranges).filter(|Coord(coord)| coord[0] == *ranges[0].start() && coord[1] == *ranges[1].start()) {
+        let labels: Vec<String> = (2..N).map(|axis| {
+            let name = match axis {
+                2 => "z".to_string(),
+                3 => "w".to_string(),
+                _ => format!("axis {}", axis)
+            };
 
-        if y_max.is_none() || y > y_max.unwrap() {
-            y_max = Some(y);
-        }
+            format!("{} = {}", name, slice.0[axis])
+        }).collect();
 
-        if z_max.is_none() || z > z_max.unwrap() {
-            z_max = Some(z);
-        }
+        writeln!(out, "{}", labels.join(", "))?;
+
+        for y in ranges[1].clone() {
+            for x in ranges[0].clone() {
+                let mut coord = slice;
+                coord.0[0] = x;
+                coord.0[1] = y;
 
-        if w_max.is_none() || w > w_max.unwrap() {
-            w_max = Some(w);
+                write!(out, "{}", if data.contains(&coord) { "#" } else { "." })?;
+            }
+
+            writeln!(out)?;
         }
+
+        writeln!(out)?;
     }
 
-    (
-        x_min.unwrap() - 1 ..= x_max.unwrap() + 1,
-        y_min.unwrap() - 1 ..= y_max.unwrap() + 1,
-        z_min.unwrap() - 1 ..= z_max.unwrap() + 1,
-        w_min.unwrap() - 1 ..= w_max.unwrap() + 1
-    )
+    Ok(())
 }
 
-fn step_one_cycle_4(input: &HashMap<Coord4, State>) -> HashMap<Coord4, State> {
-    let mut output = HashMap::new();
-
-    let (x_range, y_range, z_range, w_range) = coord_range_4(input);
-
-    for x in x_range {
-        for y in y_range.clone() {
-            for z in z_range.clone() {
-                for w in w_range.clone() {
-                    let coord = (x, y, z, w);
-
-                    let (actives, _inactives) = count_neighboring_states_4(input, &coord);
-
-                    let current_state = input.get(&coord).unwrap_or(&State::Inactive);
-
-                    match current_state {
-                        State::Active => {
-                            if actives == 2 || actives == 3 {
-                                output.insert(coord, State::Active);
-                            } else {
-                                output.insert(coord, State::Inactive);
-                            }
-                        },
-                        State::Inactive => {
-                            if actives == 3 {
-                                output.insert(coord, State::Active);
-                            } else {
-                                output.insert(coord, State::Inactive);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+#[aoc(day17, part1)]
+fn six_cycles_active_count(input: &ActiveSet<2>) -> usize {
+    active_count_after::<3>(input, 6, Symmetry::MirrorExtraAxes)
+}
 
-    output
+#[aoc(day17, part2)]
+fn hypercube_six_cycles_active_count(input: &ActiveSet<2>) -> usize {
+    active_count_after::<4>(input, 6, Symmetry::MirrorExtraAxes)
 }
 
-fn step_n_cycles_4(input: &HashMap<Coord4, State>, n: usize) -> HashMap<Coord4, State> {
-    let mut output = input.clone();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for _ in 0..n {
-        output = step_one_cycle_4(&output);
+    fn example() -> ActiveSet<2> {
+        parse_input(include_str!("../input/2020/day17_example.txt"))
     }
 
-    output.clone()
-}
+    #[test]
+    fn matches_puzzle_examples() {
+        for &symmetry in &[Symmetry::None, Symmetry::MirrorExtraAxes] {
+            assert_eq!(active_count_after::<3>(&example(), 6, symmetry), 112);
+            assert_eq!(active_count_after::<4>(&example(), 6, symmetry), 848);
+        }
+    }
 
-#[aoc(day17, part2)]
-fn hypercube_six_cycles_active_count(input: &HashMap<Coord3, State>) -> usize {
-    let hypercube = map_to_hypercube(input);
+    #[test]
+    fn folding_matches_full_simulation() {
+        let mut full: PocketDimension<5> = PocketDimension::new(&example(), Symmetry::None);
+        let mut folded: PocketDimension<5> = PocketDimension::new(&example(), Symmetry::MirrorExtraAxes);
 
-    let result = step_n_cycles_4(&hypercube, 6);
+        for _ in 0..=4 {
+            assert_eq!(folded.active_count(), full.active_count());
+            assert_eq!(folded.active_cells(), full.active_cells());
 
-    result.values().filter(|&&value| {
-        match value {
-            State::Active => true,
-            _ => false
+            full.step();
+            folded.step();
         }
-    }).count()
+    }
 }
@@ -1,16 +1,31 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use regex::Regex;
+use serde_json::{json, Map, Value};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
 
 type Color = String;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct BagType {
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BagType {
     modifier: String,
     color: Color
 }
 
-type Ruleset = HashMap<BagType, Vec<(usize, BagType)>>;
+impl BagType {
+    pub fn new(modifier: &str, color: &str) -> BagType {
+        BagType { modifier: modifier.to_string(), color: color.to_string() }
+    }
+}
+
+impl fmt::Display for BagType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.modifier, self.color)
+    }
+}
+
+pub type Ruleset = HashMap<BagType, Vec<(usize, BagType)>>;
 
 fn parse_bag_type_with_count(input: &str) -> (usize, BagType) {
     let re = Regex::new(r"(\d+) (\w+) (\w+) (?:bag|bags)").unwrap();
@@ -25,7 +40,7 @@ fn parse_bag_type_with_count(input: &str) -> (usize, BagType) {
 }
 
 #[aoc_generator(day7)]
-fn parse_rules(input: &str) -> Ruleset {
+pub fn parse_rules(input: &str) -> Ruleset {
     let re = Regex::new(r"^(\w+) (\w+) bags contain (.*).$").unwrap();
 
     input.lines().map(|line| {
@@ -65,7 +80,7 @@ fn count_rules_with_bag(rules: &Ruleset) -> usize {
     let mut to_lookup: HashSet<BagType> = HashSet::new();
     let mut found: HashSet<BagType> = HashSet::new();
 
-    to_lookup.insert(BagType { modifier: String::from("shiny"), color: String::from("gold") });
+    to_lookup.insert(BagType::new("shiny", "gold"));
 
     while !to_lookup.is_empty() {
         let frontier = to_lookup.clone();
@@ -110,8 +125,75 @@ fn count_bags_for_target(
 fn count_bags(rules: &Ruleset) -> usize {
     let mut cache: HashMap<BagType, usize> = HashMap::new();
 
-    let target = BagType { modifier: String::from("shiny"), color: String::from("gold") };
+    let target = BagType::new("shiny", "gold");
 
     count_bags_for_target(rules, &mut cache, &target)
 }
 
+
+// every bag that can end up (transitively) inside `start`, including `start` itself
+fn reachable_from(rules: &Ruleset, start: &BagType) -> HashSet<BagType> {
+    let mut found: HashSet<BagType> = HashSet::new();
+    let mut to_visit = vec![start.clone()];
+
+    while let Some(bag_type) = to_visit.pop() {
+        if found.insert(bag_type.clone()) {
+            if let Some(contained) = rules.get(&bag_type) {
+                to_visit.extend(contained.iter().map(|(_, b)| b.clone()));
+            }
+        }
+    }
+
+    found
+}
+
+fn sorted_bag_types(rules: &Ruleset) -> Vec<&BagType> {
+    let mut bag_types: Vec<&BagType> = rules.keys().collect();
+    bag_types.sort();
+
+    bag_types
+}
+
+pub fn write_dot<W: Write>(rules: &Ruleset, highlight: Option<&BagType>, out: &mut W) -> io::Result<()> {
+    let highlighted = highlight.map(|bag_type| reachable_from(rules, bag_type)).unwrap_or_default();
+
+    writeln!(out, "digraph luggage {{")?;
+
+    for bag_type in sorted_bag_types(rules) {
+        if highlighted.contains(bag_type) {
+            writeln!(out, "    \"{}\" [style=filled, fillcolor=gold];", bag_type)?;
+        } else {
+            writeln!(out, "    \"{}\";", bag_type)?;
+        }
+    }
+
+    for bag_type in sorted_bag_types(rules) {
+        for (count, contained) in rules.get(bag_type).unwrap() {
+            if highlighted.contains(bag_type) {
+                writeln!(out, "    \"{}\" -> \"{}\" [label={}, color=gold];", bag_type, contained, count)?;
+            } else {
+                writeln!(out, "    \"{}\" -> \"{}\" [label={}];", bag_type, contained, count)?;
+            }
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+pub fn to_json(rules: &Ruleset) -> Value {
+    let adjacency: Map<String, Value> = sorted_bag_types(rules).into_iter().map(|bag_type| {
+        let contained: Vec<Value> = rules.get(bag_type).unwrap().iter().map(|(count, b)| {
+            json!({ "bag": b.to_string(), "count": count })
+        }).collect();
+
+        (bag_type.to_string(), Value::Array(contained))
+    }).collect();
+
+    Value::Object(adjacency)
+}
+
+pub fn write_json<W: Write>(rules: &Ruleset, out: &mut W) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, &to_json(rules))?;
+
+    writeln!(out)
+}
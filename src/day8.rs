@@ -1,107 +1,133 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashMap;
+use crate::console::{assemble, next_pc, terminating_pcs, Instr, Program, RunResult, Vm};
 
-#[derive(Debug, Copy, Clone)]
-enum Instr {
-    Acc(isize),
-    Jmp(isize),
-    Nop(isize),
+#[aoc_generator(day8)]
+pub fn parse_program(input: &str) -> Program {
+    assemble(input).unwrap_or_else(|err| panic!("{}", err))
 }
 
-type Program = Vec<Instr>;
-
-#[aoc_generator(day8)]
-fn parse_program(input: &str) -> Program {
-    input.lines().map(|line| {
-        match &line[0..3] {
-            "nop" => Instr::Nop(line[4..].parse().unwrap()),
-            "acc" => Instr::Acc(line[4..].parse().unwrap()),
-            "jmp" => Instr::Jmp(line[4..].parse().unwrap()),
-            _ => unreachable!("unexpected program instruction")
-        }
-    }).collect()
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Repair {
+    pub pc: usize,
+    pub original: Instr,
+    pub replacement: Instr,
+    pub acc: isize,
 }
 
-enum RunResult {
-    Terminated(isize),
-    Loop(isize)
+impl Repair {
+    pub fn apply(&self, program: &Program) -> Program {
+        let mut new_program = program.clone();
+        new_program[self.pc] = self.replacement;
+
+        new_program
+    }
 }
 
-fn run_program_once(program: &Program) -> RunResult {
-    let len = program.len();
+#[aoc(day8, part1)]
+fn first_iteration_acc(program: &Program) -> isize {
+    let rr = Vm::new(program.clone()).run();
 
-    let mut acc = 0isize;
-    let mut pc = 0usize;
+    match rr {
+        RunResult::Loop { acc, .. } => acc,
+        _ => unreachable!("unexpected run result")
+    }
+}
 
-    let mut visited: HashMap<usize, usize> = HashMap::new();
+// a program that already terminates has nothing to repair, so both searches return `None` for it
+pub fn repair_brute_force(program: &Program) -> Option<Repair> {
+    if let RunResult::Terminated(_) = Vm::new(program.clone()).run() {
+        return None;
+    }
 
-    while visited.get(&pc).unwrap_or(&0) == &0 {
-        *visited.entry(pc).or_insert(0) += 1;
+    program.iter().enumerate().find_map(|(pc, &original)| {
+        let replacement = original.flipped()?;
 
-        if pc == len {
-            return RunResult::Terminated(acc)
+        let mut new_program = program.clone();
+        new_program[pc] = replacement;
+
+        match Vm::new(new_program).run() {
+            RunResult::Terminated(acc) => Some(Repair { pc, original, replacement, acc }),
+            _ => None
         }
+    })
+}
 
-        assert!(pc < len);
+// Once the boot code is known not to terminate, the flip has to be on the path it actually
+// executes. Flipping an instruction there fixes the program exactly when its new target already
+// reaches the end: the flipped instruction can't be on that onward route, or the original path
+// would have terminated.
+pub fn repair_by_control_flow(program: &Program) -> Option<Repair> {
+    let terminating = terminating_pcs(program);
 
-        let cmd = program[pc];
-        match cmd {
-            Instr::Nop(_) => {
-                pc += 1;
-            },
-            Instr::Acc(x) => {
-                acc += x;
-                pc += 1
-            }
-            Instr::Jmp(j) => {
-                pc = (pc as isize + j) as usize;
-            }
-        }
+    if program.is_empty() || terminating[0] {
+        return None;
     }
+    let reaches_end = |target: isize| {
+        target == program.len() as isize
+            || (0 <= target && (target as usize) < program.len() && terminating[target as usize])
+    };
 
-    RunResult::Loop(acc)
-}
+    let mut visited = vec![false; program.len()];
+    let mut pc = 0;
 
-#[aoc(day8, part1)]
-fn first_iteration_acc(program: &Program) -> isize {
-    let rr = run_program_once(program);
+    while pc < program.len() && !visited[pc] {
+        visited[pc] = true;
 
-    match rr {
-        RunResult::Loop(acc) => acc,
-        _ => unreachable!("unexpected run result")
+        let original = program[pc];
+
+        if let Some(replacement) = original.flipped() {
+            if reaches_end(next_pc(pc, replacement)) {
+                let mut new_program = program.clone();
+                new_program[pc] = replacement;
+
+                return match Vm::new(new_program).run() {
+                    RunResult::Terminated(acc) => Some(Repair { pc, original, replacement, acc }),
+                    _ => None
+                };
+            }
+        }
+
+        match next_pc(pc, original) {
+            target if target < 0 => return None,
+            target => { pc = target as usize; }
+        }
     }
+
+    None
 }
 
 #[aoc(day8, part2)]
 fn terminate_acc(program: &Program) -> isize {
-    let mut res: Option<isize> = None;
+    repair_by_control_flow(program).unwrap().acc
+}
 
-    program.iter().enumerate().find(|&(idx, &instr)| {
-        let new_instr = match instr {
-            Instr::Nop(j) => Some(Instr::Jmp(j)),
-            Instr::Jmp(j) => Some(Instr::Nop(j)),
-            Instr::Acc(_) => None
-        };
+#[aoc(day8, part2, brute_force)]
+fn terminate_acc_brute_force(program: &Program) -> isize {
+    repair_brute_force(program).unwrap().acc
+}
 
-        if new_instr.is_none() {
-            false
-        } else {
-            let new_instr = new_instr.unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            let mut new_program = program.clone();
-            new_program.splice(idx..idx+1, vec![new_instr]);
+    const EXAMPLE: &str = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
 
-            let result = run_program_once(&new_program);
+    #[test]
+    fn control_flow_repair_matches_brute_force() {
+        let program = parse_program(EXAMPLE);
+        let repair = repair_by_control_flow(&program);
 
-            if let RunResult::Terminated(acc) = result {
-                res = Some(acc);
+        assert_eq!(repair, repair_brute_force(&program));
+        assert_eq!(repair.map(|r| (r.pc, r.acc)), Some((7, 8)));
+    }
 
-                true
-            } else {
-                false
-            }
-        }
-    });
+    #[test]
+    fn terminating_program_needs_no_repair() {
+        for source in &["nop +0", "nop +1\nacc +1", ""] {
+            let program = parse_program(source);
 
-    res.unwrap()
+            assert_eq!(repair_by_control_flow(&program), None);
+            assert_eq!(repair_brute_force(&program), None);
+        }
+    }
 }
@@ -1,56 +1,155 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead};
+
+const PREAMBLE_LEN: usize = 25;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum XmasError {
+    NoInvalidNumber,
+    NoContiguousRange(usize),
+}
+
+impl fmt::Display for XmasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XmasError::NoInvalidNumber => write!(f, "every number is a sum of two in its preamble"),
+            XmasError::NoContiguousRange(target) => write!(f, "no contiguous range sums to {}", target),
+        }
+    }
+}
 
 #[aoc_generator(day9)]
-fn parse_to_numbers(input: &str) -> Vec<usize> {
+pub fn parse_to_numbers(input: &str) -> Vec<usize> {
     input.lines().map(|l| l.parse().unwrap()).collect()
 }
 
-fn is_sum_of_two(preamble: &Vec<usize>, sum: usize) -> bool {
-    preamble.iter().enumerate().any(|(i, val1)| {
-        preamble.iter().skip(i).enumerate().any(|(_, val2)| {
-            val1 + val2 == sum
-        })
-    })
+// Sliding window over the last `preamble_len` numbers, along with a multiset of the sums of
+// every pair of distinct values in it, so each new number costs O(preamble_len) to check.
+#[derive(Debug, Clone)]
+pub struct XmasValidator {
+    preamble_len: usize,
+    window: VecDeque<usize>,
+    pair_sums: HashMap<usize, usize>,
 }
 
-#[aoc(day9, part1)]
-fn first_not_matching(inputs: &Vec<usize>) -> usize {
-    let mut preamble: Vec<usize> = inputs.iter().take(25).cloned().collect();
+impl XmasValidator {
+    pub fn new(preamble_len: usize) -> XmasValidator {
+        XmasValidator {
+            preamble_len,
+            window: VecDeque::with_capacity(preamble_len),
+            pair_sums: HashMap::new(),
+        }
+    }
+
+    // false if the window is full and `value` isn't the sum of two different numbers in it
+    pub fn push(&mut self, value: usize) -> bool {
+        if self.window.len() < self.preamble_len {
+            self.add(value);
+
+            return true;
+        }
+
+        let valid = self.pair_sums.contains_key(&value);
+
+        if let Some(oldest) = self.window.pop_front() {
+            for &other in self.window.iter().filter(|&&other| other != oldest) {
+                let sum = oldest + other;
+                let count = self.pair_sums.get_mut(&sum).unwrap();
+
+                *count -= 1;
 
-    for &elem in inputs.iter().skip(25) {
-        if !is_sum_of_two(&preamble, elem) {
-            return elem;
-        } else {
-            preamble.drain(0..1);
-            preamble.push(elem);
+                if *count == 0 {
+                    self.pair_sums.remove(&sum);
+                }
+            }
         }
+
+        self.add(value);
+
+        valid
     }
 
-    0
+    fn add(&mut self, value: usize) {
+        for &other in self.window.iter().filter(|&&other| other != value) {
+            *self.pair_sums.entry(value + other).or_insert(0) += 1;
+        }
+
+        self.window.push_back(value);
+    }
 }
 
-fn contiguous_subsequence_sum(inputs: &Vec<usize>, target: usize) -> Option<&[usize]> {
-    for (i, _) in inputs.iter().enumerate() {
-        for (j, _) in inputs.iter().enumerate().skip(i) {
-            let subseq = &inputs[i..j];
+pub fn first_invalid_in<I: IntoIterator<Item = usize>>(inputs: I, preamble_len: usize) -> Option<usize> {
+    let mut validator = XmasValidator::new(preamble_len);
 
-            if subseq.iter().sum::<usize>() == target {
-                return Some(subseq)
-            }
+    inputs.into_iter().find(|&value| !validator.push(value))
+}
+
+// reads one number per line, so arbitrarily long streams never need to be held in memory
+pub fn first_invalid_from_reader<R: BufRead>(reader: R, preamble_len: usize) -> io::Result<Option<usize>> {
+    let mut validator = XmasValidator::new(preamble_len);
+
+    for line in reader.lines() {
+        let value = line?.trim().parse()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        if !validator.push(value) {
+            return Ok(Some(value));
+        }
+    }
+
+    Ok(None)
+}
+
+pub fn first_invalid(inputs: &[usize], preamble_len: usize) -> Option<usize> {
+    first_invalid_in(inputs.iter().cloned(), preamble_len)
+}
+
+#[aoc(day9, part1)]
+fn first_not_matching(inputs: &Vec<usize>) -> usize {
+    first_invalid(inputs, PREAMBLE_LEN).unwrap_or_else(|| panic!("{}", XmasError::NoInvalidNumber))
+}
+
+// two pointers over a run of (non-negative) numbers, keeping only the current window around;
+// the range has to contain at least two numbers
+pub fn contiguous_range_in<I: IntoIterator<Item = usize>>(inputs: I, target: usize) -> Option<Vec<usize>> {
+    let mut window: VecDeque<usize> = VecDeque::new();
+    let mut sum = 0;
+
+    for value in inputs {
+        window.push_back(value);
+        sum += value;
+
+        while sum > target {
+            sum -= window.pop_front().unwrap();
         }
-    };
+
+        if sum == target && window.len() >= 2 {
+            return Some(window.into_iter().collect());
+        }
+    }
 
     None
 }
 
-#[aoc(day9, part2)]
-fn encryption_weakness(inputs: &Vec<usize>) -> usize {
-    let target = 257342611;
+pub fn contiguous_subsequence_sum(inputs: &[usize], target: usize) -> Option<Vec<usize>> {
+    contiguous_range_in(inputs.iter().cloned(), target)
+}
+
+pub fn weakness(inputs: &[usize], preamble_len: usize) -> Result<usize, XmasError> {
+    let target = first_invalid(inputs, preamble_len).ok_or(XmasError::NoInvalidNumber)?;
 
-    let subseq = contiguous_subsequence_sum(inputs, target).unwrap();
+    let subseq = contiguous_subsequence_sum(inputs, target)
+        .ok_or(XmasError::NoContiguousRange(target))?;
 
     let max = subseq.iter().max().unwrap();
     let min = subseq.iter().min().unwrap();
 
-    min + max
+    Ok(min + max)
+}
+
+#[aoc(day9, part2)]
+fn encryption_weakness(inputs: &Vec<usize>) -> usize {
+    weakness(inputs, PREAMBLE_LEN).unwrap_or_else(|err| panic!("{}", err))
 }